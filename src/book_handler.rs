@@ -1,57 +1,86 @@
-use crate::utils::get_if_available;
+use crate::error::{Error, Result};
+use crate::index::ChapterIndex;
+use crate::render;
+use crate::summary::{ChapterLink, Placement, SummaryEditor};
+use crate::types::BookSettings;
+use crate::utils::command_line;
 use mdbook::{
     book::{BookItem, Chapter, SectionNumber},
     config::Config,
     MDBook,
 };
-use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
+use slug::slugify;
+use std::fs::{self, File};
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
+/// Table in `book.toml` holding sanctuary's per-book settings.
+const SETTINGS_TABLE: &str = "sanctuary";
+
 pub trait Handler {
-    fn add_chapter(&mut self, path: &str, name: &str, parents: Option<Vec<String>>);
+    fn add_chapter(&mut self, path: &str, name: &str, parents: Option<Vec<String>>) -> Result<()>;
     fn get_sum_path(&self) -> PathBuf;
-    fn add_chapter_to_summary(&self, filename: &str, name: &str);
+    fn add_chapter_to_summary(
+        &self,
+        filename: &str,
+        name: &str,
+        parent: Option<&Path>,
+    ) -> Result<()>;
     fn get_chapter_paths(&self) -> Vec<PathBuf>;
     fn get_chapters(&self) -> Vec<&Chapter>;
-    fn index_chapters(&self) -> HashMap<u32, Chapter>;
-    fn get_chapter_path(&self, id: u32, map: &HashMap<u32, Chapter>) -> PathBuf;
-    fn prettyprint_chapter(&self, id: u32, map: &HashMap<u32, Chapter>);
+    fn find_chapter(&self, name: &str) -> Result<&Chapter>;
+    fn index_chapters(&self) -> ChapterIndex;
+    fn get_chapter_path(&self, chapter: &Chapter) -> PathBuf;
+    fn prettyprint_chapter(&self, chapter: &Chapter, viewer: Option<&str>) -> Result<()>;
     fn get_chapter_nums(&self) -> Vec<SectionNumber>;
     fn get_parents(&self) -> Vec<Vec<String>>;
     fn get_chapter_names(&self) -> Vec<String>;
-    fn create_idea_book(
-        path: PathBuf,
-        title: String,
-        author: String,
-    ) -> mdbook::errors::Result<MDBook>;
+    fn create_idea_book(path: PathBuf, title: String, author: String) -> Result<MDBook>;
+    fn book_settings(&self) -> Result<BookSettings>;
+    fn new_chapter_path(&self, title: &str, parent: Option<&Path>) -> Result<PathBuf>;
 }
 impl Handler for MDBook {
-    fn add_chapter(&mut self, path: &str, name: &str, parents: Option<Vec<String>>) {
+    fn add_chapter(&mut self, path: &str, name: &str, parents: Option<Vec<String>>) -> Result<()> {
         let path_buf = PathBuf::from(path);
-        let mut chapterfile = File::open(&path_buf).unwrap();
+        let mut chapterfile = File::open(&path_buf)?;
         let mut chaptercontent = String::new();
-        let relativepath = path_buf.file_name().unwrap();
-        chapterfile.read_to_string(&mut chaptercontent).unwrap();
+        let relativepath = path_buf
+            .strip_prefix(self.source_dir())
+            .ok()
+            .or_else(|| path_buf.file_name().map(Path::new))
+            .ok_or_else(|| Error::ChapterNotFound(path.to_string()))?;
+        chapterfile.read_to_string(&mut chaptercontent)?;
         let chapter = match parents {
             Some(pars) => Chapter::new(name, chaptercontent, relativepath, pars),
             None => Chapter::new(name, chaptercontent, relativepath, Vec::new()),
         };
         self.book.push_item(chapter);
+        Ok(())
     }
 
     fn get_sum_path(&self) -> PathBuf {
         let mut srcpath = self.source_dir();
         srcpath.push("SUMMARY.md");
-        return srcpath;
+        srcpath
     }
 
-    fn add_chapter_to_summary(&self, filename: &str, name: &str) {
-        let sumpath = self.get_sum_path();
-        let mut f = OpenOptions::new().append(true).open(sumpath).unwrap();
-        writeln!(f, "- [{}](./{})", name, filename).unwrap();
+    /// Adds a chapter to `SUMMARY.md`, as the last sub-chapter of `parent`
+    /// when given.
+    fn add_chapter_to_summary(
+        &self,
+        filename: &str,
+        name: &str,
+        parent: Option<&Path>,
+    ) -> Result<()> {
+        let mut summary = SummaryEditor::load(&self.get_sum_path())?;
+        let location = Path::new(".").join(filename);
+        let placement = match parent {
+            Some(parent) => Placement::Under(parent.to_path_buf()),
+            None => Placement::End,
+        };
+        summary.insert(ChapterLink::new(name, location), placement)?;
+        summary.save()
     }
 
     fn get_chapter_paths(&self) -> Vec<PathBuf> {
@@ -76,36 +105,69 @@ impl Handler for MDBook {
         out
     }
 
-    fn index_chapters(&self) -> HashMap<u32, Chapter> {
-        let chaps = self.get_chapters();
-        let mut out: HashMap<u32, Chapter> = HashMap::new();
-        for chap in chaps {
-            let num = chap.number.clone().unwrap()[0];
-            out.insert(num, chap.to_owned());
+    /// Finds a chapter by its name, ignoring case, or by its path relative to
+    /// the source directory.
+    fn find_chapter(&self, name: &str) -> Result<&Chapter> {
+        let wanted = Path::new(name);
+        let wanted = wanted.strip_prefix(".").unwrap_or(wanted);
+        let matches: Vec<&Chapter> = self
+            .get_chapters()
+            .into_iter()
+            .filter(|chap| chap.name.to_lowercase() == name.to_lowercase() || chap.path == wanted)
+            .collect();
+        match matches.as_slice() {
+            [chapter] => Ok(chapter),
+            [] => Err(Error::ChapterNotFound(name.to_string())),
+            _ => Err(Error::AmbiguousChapter {
+                name: name.to_string(),
+                paths: matches
+                    .iter()
+                    .map(|chap| chap.path.display().to_string())
+                    .collect(),
+            }),
         }
-        out
     }
 
-    fn get_chapter_path(&self, id: u32, map: &HashMap<u32, Chapter>) -> PathBuf {
-        let chap = map.get(&id).unwrap();
+    fn index_chapters(&self) -> ChapterIndex {
+        ChapterIndex::new(self)
+    }
+
+    fn get_chapter_path(&self, chapter: &Chapter) -> PathBuf {
         let mut bookpath = self.source_dir();
-        bookpath.push(chap.path.clone());
+        bookpath.push(&chapter.path);
         bookpath
     }
 
-    fn prettyprint_chapter(&self, id: u32, map: &HashMap<u32, Chapter>) {
-        let path = self.get_chapter_path(id, map);
-        let bat_path =
-            get_if_available("bat").expect("Cannot locate executable - less - on your system");
-        match Command::new(bat_path).arg(&path).status() {
-            Ok(_) => {}
-            Err(e) => {
-                eprintln!(
-                    "Error: Could not open idea file with bat at [{}]: {}",
-                    path.to_str().unwrap(),
-                    e
-                );
-            }
+    /// Shows a chapter with the `viewer` command line, or renders it in the
+    /// terminal when there is no viewer.
+    fn prettyprint_chapter(&self, chapter: &Chapter, viewer: Option<&str>) -> Result<()> {
+        let path = self.get_chapter_path(chapter);
+        let viewer = match viewer {
+            Some(viewer) => viewer,
+            None => return Ok(render::show(&fs::read_to_string(&path)?)?),
+        };
+
+        let args =
+            command_line(viewer, &[("path", &path.to_string_lossy())]).map_err(|reason| {
+                Error::Viewer {
+                    viewer: viewer.to_string(),
+                    reason,
+                }
+            })?;
+        let status = Command::new(&args[0])
+            .args(&args[1..])
+            .status()
+            .map_err(|e| Error::Viewer {
+                viewer: viewer.to_string(),
+                reason: e.to_string(),
+            })?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::Viewer {
+                viewer: viewer.to_string(),
+                reason: format!("exited with {}", status),
+            })
         }
     }
 
@@ -113,7 +175,7 @@ impl Handler for MDBook {
         let mut out: Vec<SectionNumber> = Vec::new();
         for item in self.book.iter() {
             match *item {
-                BookItem::Chapter(ref chapter) => out.push(chapter.number.clone().unwrap()),
+                BookItem::Chapter(ref chapter) => out.extend(chapter.number.clone()),
                 BookItem::Separator => {}
             }
         }
@@ -143,17 +205,104 @@ impl Handler for MDBook {
         out
     }
 
-    fn create_idea_book(
-        path: PathBuf,
-        title: String,
-        author: String,
-    ) -> mdbook::errors::Result<MDBook> {
+    fn create_idea_book(path: PathBuf, title: String, author: String) -> Result<MDBook> {
         let mut cfg = Config::default();
         cfg.book.title = Some(title);
         cfg.book.authors.push(author);
-        MDBook::init(path)
+        MDBook::init(path.clone())
             .create_gitignore(true)
             .with_config(cfg)
             .build()
+            .map_err(|source| Error::book(path, source))
+    }
+
+    fn book_settings(&self) -> Result<BookSettings> {
+        self.config
+            .get_deserialized_opt(SETTINGS_TABLE)
+            .map(Option::unwrap_or_default)
+            .map_err(|source| Error::book(self.root.clone(), source))
+    }
+
+    /// Picks a file for a new chapter titled `title`, relative to the source
+    /// directory. The file name is a slug of the title, suffixed with `-2`,
+    /// `-3`, ... when a chapter or file already uses it. Sub-chapters go in a
+    /// directory named after their `parent`'s file.
+    fn new_chapter_path(&self, title: &str, parent: Option<&Path>) -> Result<PathBuf> {
+        let dir: PathBuf = match (parent, self.book_settings()?.subdir) {
+            (Some(parent), _) => parent.with_extension(""),
+            (None, Some(subdir)) => subdir
+                .split('/')
+                .map(slugify)
+                .filter(|component| !component.is_empty())
+                .collect(),
+            (None, None) => PathBuf::new(),
+        };
+        let stem = match slugify(title) {
+            ref stem if stem.is_empty() => "idea".to_string(),
+            stem => stem,
+        };
+
+        let taken = self.get_chapter_paths();
+        let src_dir = self.source_dir();
+        let mut n = 1;
+        loop {
+            let file_name = if n == 1 {
+                format!("{}.md", stem)
+            } else {
+                format!("{}-{}.md", stem, n)
+            };
+            let path = dir.join(file_name);
+            if !taken.contains(&path) && !src_dir.join(&path).exists() {
+                return Ok(path);
+            }
+            n += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn new_chapter_paths_are_unique_slugs() {
+        let dir = TempDir::new().unwrap();
+        let mut book = MDBook::create_idea_book(
+            dir.path().to_path_buf(),
+            "Ideas".to_string(),
+            "Ada".to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            book.new_chapter_path("Why / not? Crème brûlée", None)
+                .unwrap(),
+            PathBuf::from("why-not-creme-brulee.md")
+        );
+        fs::write(book.source_dir().join("idea.md"), "# Idea\n").unwrap();
+        assert_eq!(
+            book.new_chapter_path("Idea", None).unwrap(),
+            PathBuf::from("idea-2.md")
+        );
+        fs::write(book.source_dir().join("idea-2.md"), "# Idea\n").unwrap();
+        assert_eq!(
+            book.new_chapter_path("???", None).unwrap(),
+            PathBuf::from("idea-3.md")
+        );
+
+        book.config
+            .set("sanctuary.subdir", "Inbox/../2020")
+            .unwrap();
+        assert_eq!(
+            book.new_chapter_path("Idea", None).unwrap(),
+            PathBuf::from("inbox/2020/idea.md")
+        );
+        assert_eq!(
+            book.new_chapter_path("Bread", Some(Path::new("cooking.md")))
+                .unwrap(),
+            PathBuf::from("cooking/bread.md")
+        );
     }
 }
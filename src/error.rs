@@ -0,0 +1,146 @@
+use crate::git::GitError;
+use crate::library::LIBRARY_VERSION;
+use crate::types::ConfigFile;
+use std::path::PathBuf;
+use std::{error, fmt, io, result};
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A setting needed for the operation has not been configured yet.
+    ConfigMissing(ConfigFile),
+    /// mdBook could not load, create or build the book at `path`.
+    Book {
+        path: PathBuf,
+        source: Box<mdbook::errors::Error>,
+    },
+    /// A chapter was requested that the book does not contain.
+    ChapterNotFound(String),
+    /// A chapter was requested by a name several chapters share.
+    AmbiguousChapter {
+        name: String,
+        paths: Vec<String>,
+    },
+    /// The editor could not be found or run.
+    Editor {
+        editor: String,
+        reason: String,
+    },
+    /// The program configured to view chapters could not be run or failed.
+    Viewer {
+        viewer: String,
+        reason: String,
+    },
+    /// A git command could not be run or exited unsuccessfully.
+    Git(GitError),
+    /// Rebasing onto the remote hit conflicts in these chapters and was aborted.
+    SyncConflict(Vec<String>),
+    /// A path given as a book has no `book.toml`.
+    NotABook(PathBuf),
+    /// The library already has a book with this name or path.
+    DuplicateBook {
+        name: String,
+        path: PathBuf,
+    },
+    /// The library has no book with this name or path.
+    BookNotFound(String),
+    /// The library file was written by a newer version of sanctuary.
+    LibraryVersion {
+        path: PathBuf,
+        version: u64,
+    },
+    /// `SUMMARY.md` could not be parsed or updated.
+    Summary {
+        path: PathBuf,
+        reason: String,
+    },
+    /// The user backed out of a choice.
+    Cancelled,
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ConfigMissing(config) => write!(
+                f,
+                "{} is not configured, run `sanctuary capture` to set it up",
+                config.value()
+            ),
+            Error::Book { path, source } => {
+                write!(f, "problem with the book at {}: {}", path.display(), source)
+            }
+            Error::ChapterNotFound(chapter) => write!(f, "no chapter {} in this book", chapter),
+            Error::AmbiguousChapter { name, paths } => write!(
+                f,
+                "several chapters are called {}, pick one by path: {}",
+                name,
+                paths.join(", ")
+            ),
+            Error::Editor { editor, reason } => write!(f, "editor {} failed: {}", editor, reason),
+            Error::Viewer { viewer, reason } => write!(f, "viewer {} failed: {}", viewer, reason),
+            Error::Git(e) => write!(f, "{}", e),
+            Error::SyncConflict(chapters) => write!(
+                f,
+                "sync aborted, these chapters changed both here and on the remote: {}",
+                chapters.join(", ")
+            ),
+            Error::NotABook(path) => {
+                write!(
+                    f,
+                    "{} is not an mdBook, it has no book.toml",
+                    path.display()
+                )
+            }
+            Error::DuplicateBook { name, path } => {
+                write!(f, "the library already has {} at {}", name, path.display())
+            }
+            Error::BookNotFound(book) => write!(f, "no book {} in the library", book),
+            Error::LibraryVersion { path, version } => write!(
+                f,
+                "the library at {} is version {}, newer than the {} this sanctuary reads",
+                path.display(),
+                version,
+                LIBRARY_VERSION
+            ),
+            Error::Summary { path, reason } => {
+                write!(f, "could not update {}: {}", path.display(), reason)
+            }
+            Error::Cancelled => write!(f, "nothing was chosen"),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Book { source, .. } => Some(source.as_ref()),
+            Error::Git(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    pub(crate) fn book<P: Into<PathBuf>>(path: P, source: mdbook::errors::Error) -> Self {
+        Error::Book {
+            path: path.into(),
+            source: Box::new(source),
+        }
+    }
+}
+
+impl From<GitError> for Error {
+    fn from(e: GitError) -> Self {
+        Error::Git(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
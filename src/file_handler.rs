@@ -1,16 +1,85 @@
 extern crate dirs;
 
-use self::dirs::home_dir;
-use crate::types::ConfigFile;
+use self::dirs::{config_dir, home_dir};
+use crate::types::{ConfigFile, Settings};
+use std::env;
+use std::ffi::OsString;
 use std::io::{ErrorKind, Read, Write};
-use std::{fs, io, path};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
 
-pub struct FileHandler;
+const CONFIG_FILE_NAME: &str = "config.toml";
+const LIBRARY_FILE_NAME: &str = "library.json";
+const CONFIG_DIR_NAME: &str = "sanctuary";
+const LEGACY_DIR_NAME: &str = ".eureka";
+
+/// Environment variable that overrides where the config directory lives.
+pub const CONFIG_ROOT_ENV: &str = "SANCTUARY_HOME";
+
+pub struct FileHandler {
+    /// Directory holding the config file.
+    pub root: PathBuf,
+    /// Directory older versions stored their per-value files in, if any
+    /// should be migrated from.
+    pub legacy_root: Option<PathBuf>,
+}
+
+impl FileHandler {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        FileHandler {
+            root: root.into(),
+            legacy_root: None,
+        }
+    }
+
+    pub fn with_legacy_root<P: Into<PathBuf>>(mut self, legacy_root: P) -> Self {
+        self.legacy_root = Some(legacy_root.into());
+        self
+    }
+
+    /// Resolves the config root from `override_dir` (the `--config-dir` flag),
+    /// then `$SANCTUARY_HOME`, then `$XDG_CONFIG_HOME/sanctuary` and finally
+    /// the platform config directory.
+    pub fn from_env(override_dir: Option<&str>) -> io::Result<Self> {
+        FileHandler::from_lookup(
+            override_dir,
+            |name| env::var_os(name),
+            config_dir(),
+            home_dir(),
+        )
+    }
+
+    /// Builds the handler like `from_env`, reading environment variables with
+    /// `var`. Settings are only migrated from `home` when the config root is
+    /// not overridden, so an overridden root never touches the real home.
+    fn from_lookup<F>(
+        override_dir: Option<&str>,
+        var: F,
+        platform_dir: Option<PathBuf>,
+        home: Option<PathBuf>,
+    ) -> io::Result<Self>
+    where
+        F: Fn(&str) -> Option<OsString>,
+    {
+        let overridden =
+            override_dir.is_some() || var(CONFIG_ROOT_ENV).filter(|dir| !dir.is_empty()).is_some();
+        let fh = FileHandler::new(config_root_from(override_dir, &var, platform_dir)?);
+        Ok(match home {
+            Some(home) if !overridden => fh.with_legacy_root(home.join(LEGACY_DIR_NAME)),
+            _ => fh,
+        })
+    }
+
+    fn legacy_path_for(&self, config_type: ConfigFile) -> Option<PathBuf> {
+        self.legacy_root
+            .as_ref()
+            .map(|root| root.join(config_type.value()))
+    }
+}
 
 pub trait FileManagement {
     fn create_dir(&self, path: &str) -> io::Result<()>;
     fn file_exists(&self, path: &str) -> bool;
-    fn file_rm(&self, file: ConfigFile) -> io::Result<()>;
 }
 
 impl FileManagement for FileHandler {
@@ -21,106 +90,338 @@ impl FileManagement for FileHandler {
     fn file_exists(&self, path: &str) -> bool {
         fs::metadata(path).is_ok()
     }
-
-    fn file_rm(&self, config: ConfigFile) -> io::Result<()> {
-        let config_path = config_path_for(config);
-
-        if !self.file_exists(&config_path) {
-            return Err(io::Error::new(
-                ErrorKind::NotFound,
-                format!("Path does not exist: {}", config_path),
-            ));
-        }
-
-        fs::remove_file(&config_path)?;
-        Ok(())
-    }
 }
 
 pub trait ConfigManagement {
     fn config_dir_create(&self) -> io::Result<String>;
     fn config_dir_exists(&self) -> bool;
+    fn config_file_path(&self) -> String;
+    fn library_file_path(&self) -> String;
+    fn config_load(&self) -> io::Result<Settings>;
+    fn config_save(&self, settings: &Settings) -> io::Result<()>;
     fn config_read(&self, file: ConfigFile) -> io::Result<String>;
     fn config_write(&self, file: ConfigFile, value: String) -> io::Result<()>;
+    fn config_remove(&self, file: ConfigFile) -> io::Result<()>;
+    fn config_migrate_legacy(&self) -> io::Result<Vec<ConfigFile>>;
 }
 
 impl ConfigManagement for FileHandler {
     fn config_dir_create(&self) -> io::Result<String> {
-        fs::create_dir_all(config_dir_path()).expect("Cannot create directory");
-        Ok(config_dir_path())
+        fs::create_dir_all(&self.root)?;
+        Ok(self.root.display().to_string())
     }
 
     fn config_dir_exists(&self) -> bool {
-        self.file_exists(&config_dir_path())
+        self.root.is_dir()
     }
 
-    fn config_read(&self, config: ConfigFile) -> io::Result<String> {
-        let config_path = config_path_for(config);
+    fn config_file_path(&self) -> String {
+        self.root.join(CONFIG_FILE_NAME).display().to_string()
+    }
+
+    fn library_file_path(&self) -> String {
+        self.root.join(LIBRARY_FILE_NAME).display().to_string()
+    }
+
+    fn config_load(&self) -> io::Result<Settings> {
+        let config_path = self.config_file_path();
         if !self.file_exists(&config_path) {
-            return Err(io::Error::new(
+            return Ok(Settings::default());
+        }
+        load_settings(Path::new(&config_path))
+    }
+
+    fn config_save(&self, settings: &Settings) -> io::Result<()> {
+        let contents = toml::to_string_pretty(settings)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        if !self.config_dir_exists() {
+            self.config_dir_create()?;
+        }
+        write_atomic(&self.config_file_path(), &contents)
+    }
+
+    fn config_read(&self, config: ConfigFile) -> io::Result<String> {
+        match self.config_load()?.get(config) {
+            Some(value) if !value.is_empty() => Ok(value.clone()),
+            _ => Err(io::Error::new(
                 ErrorKind::NotFound,
-                format!("File does not exist: {}", &config_path),
-            ));
+                format!(
+                    "{} is not set in {}",
+                    config.value(),
+                    self.config_file_path()
+                ),
+            )),
         }
-        let mut file = fs::File::open(&config_path)?;
+    }
 
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .unwrap_or_else(|_| panic!("Unable to read config at: {}", config_path));
+    fn config_write(&self, config: ConfigFile, value: String) -> io::Result<()> {
+        let mut settings = self.config_load()?;
+        settings.set(config, Some(value));
+        self.config_save(&settings)
+    }
 
-        if contents.is_empty() {
+    fn config_remove(&self, config: ConfigFile) -> io::Result<()> {
+        let mut settings = self.config_load()?;
+        if settings.get(config).is_none() {
             return Err(io::Error::new(
                 ErrorKind::NotFound,
-                format!("File is empty: {}", &config_path),
+                format!(
+                    "{} is not set in {}",
+                    config.value(),
+                    self.config_file_path()
+                ),
             ));
-        } else if contents.ends_with('\n') {
-            contents
-                .pop()
-                .unwrap_or_else(|| panic!("File is empty: {}", &config_path));
         }
-
-        Ok(contents)
+        settings.set(config, None);
+        self.config_save(&settings)
     }
 
-    fn config_write(&self, config: ConfigFile, value: String) -> io::Result<()> {
-        let config_path = &config_path_for(config);
-        let path = path::Path::new(config_path);
-
-        // Create file if it doesn't exist
-        let mut file = match fs::File::create(&path) {
-            Err(e) => panic!("Couldn't create {}: {}", path.display(), e.to_string()),
-            Ok(file) => file,
+    /// Imports the settings older versions kept in `~/.eureka`, either as
+    /// per-value files or as a `config.toml`, into the config file, returning
+    /// the values that were moved. Legacy files are removed once everything
+    /// in them was moved; values the config file already has are left behind.
+    fn config_migrate_legacy(&self) -> io::Result<Vec<ConfigFile>> {
+        let legacy_root = match self.legacy_root {
+            Some(ref legacy_root) if *legacy_root != self.root => legacy_root,
+            _ => return Ok(Vec::new()),
+        };
+        let legacy_config = legacy_root.join(CONFIG_FILE_NAME);
+        let legacy_settings = if legacy_config.is_file() {
+            load_settings(&legacy_config)?
+        } else {
+            Settings::default()
         };
 
-        match file.write_all(value.as_bytes()) {
-            Err(e) => panic!("Couldn't write to {}: {}", path.display(), e.to_string()),
-            Ok(_) => Ok(()),
+        let mut settings = self.config_load()?;
+        let mut moved = Vec::new();
+        let mut moved_files = Vec::new();
+
+        for config in ConfigFile::all().iter() {
+            let mut value = legacy_settings.get(*config).cloned();
+            let mut value_file = None;
+            if let Some(legacy_path) = self.legacy_path_for(*config) {
+                if legacy_path.is_file() {
+                    let mut contents = String::new();
+                    fs::File::open(&legacy_path)?.read_to_string(&mut contents)?;
+                    let contents = contents.trim_end_matches('\n');
+                    if !contents.is_empty() {
+                        value = Some(contents.to_string());
+                        value_file = Some(legacy_path);
+                    }
+                }
+            }
+
+            match value {
+                Some(value) if !value.is_empty() && settings.get(*config).is_none() => {
+                    settings.set(*config, Some(value));
+                    moved.push(*config);
+                    moved_files.extend(value_file);
+                }
+                _ => {}
+            }
         }
+
+        if !moved.is_empty() {
+            self.config_save(&settings)?;
+            // Keep the legacy config while it holds anything that was not moved
+            let left_behind = ConfigFile::all().iter().any(|config| {
+                !moved.contains(config)
+                    && legacy_settings
+                        .get(*config)
+                        .filter(|value| !value.is_empty())
+                        .is_some()
+            });
+            if legacy_config.is_file() && !left_behind {
+                fs::remove_file(&legacy_config)?;
+            }
+            for path in moved_files {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(moved)
     }
 }
 
-fn config_path_for(config_type: ConfigFile) -> String {
-    let file_name = match config_type {
-        ConfigFile::Repo => ConfigFile::Repo.value(),
-        ConfigFile::Editor => ConfigFile::Editor.value(),
-        ConfigFile::Title => ConfigFile::Title.value(),
-        ConfigFile::Author => ConfigFile::Author.value(),
-    };
-
-    match home_dir() {
-        Some(location) => format!(
-            "{home}/{eureka}/{file_name}",
-            home = location.display(),
-            eureka = ".eureka",
-            file_name = file_name
-        ),
-        None => panic!("Could not resolve your $HOME directory"),
+/// Writes `contents` to a sibling temporary file and renames it over `path`,
+/// so readers never observe a half-written file.
+pub fn write_atomic(path: &str, contents: &str) -> io::Result<()> {
+    let tmp_path = format!("{}.tmp", path);
+    {
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
     }
+    fs::rename(&tmp_path, Path::new(path))
 }
 
-fn config_dir_path() -> String {
-    match home_dir() {
-        Some(home_dir) => format!("{}/{}", home_dir.display(), ".eureka"),
-        None => panic!("Could not resolve your $HOME directory"),
+pub fn resolve_config_root(override_dir: Option<&str>) -> io::Result<PathBuf> {
+    config_root_from(override_dir, |name| env::var_os(name), config_dir())
+}
+
+/// Resolves the config root like `resolve_config_root`, reading environment
+/// variables with `var` and falling back to `platform_dir`.
+fn config_root_from<F>(
+    override_dir: Option<&str>,
+    var: F,
+    platform_dir: Option<PathBuf>,
+) -> io::Result<PathBuf>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    if let Some(dir) = override_dir {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Some(dir) = var(CONFIG_ROOT_ENV).filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    let xdg_config_home = var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute());
+    match xdg_config_home.or(platform_dir) {
+        Some(dir) => Ok(dir.join(CONFIG_DIR_NAME)),
+        None => Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "Could not resolve a config directory, set ${} or pass --config-dir",
+                CONFIG_ROOT_ENV
+            ),
+        )),
+    }
+}
+
+fn load_settings(path: &Path) -> io::Result<Settings> {
+    let mut contents = String::new();
+    fs::File::open(path)?.read_to_string(&mut contents)?;
+    toml::from_str(&contents).map_err(|e| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Unable to parse config at {}: {}", path.display(), e),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Looks environment variables up in `vars` only.
+    fn env(
+        vars: &'static [(&'static str, &'static str)],
+    ) -> impl Fn(&str) -> Option<OsString> + Copy {
+        move |name: &str| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| OsString::from(value))
+        }
+    }
+
+    #[test]
+    fn legacy_settings_are_moved_into_the_config_file() {
+        let dir = TempDir::new().unwrap();
+        let legacy = dir.path().join("legacy");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("repo_path"), "/home/ada/ideas\n").unwrap();
+        fs::write(legacy.join("editor_path"), "/usr/bin/vim\n").unwrap();
+        fs::write(legacy.join(CONFIG_FILE_NAME), "author = \"Ada\"\n").unwrap();
+        let fh = FileHandler::new(dir.path().join("config")).with_legacy_root(&legacy);
+        fh.config_write(ConfigFile::Editor, "nano".to_string())
+            .unwrap();
+
+        let moved = fh.config_migrate_legacy().unwrap();
+
+        assert_eq!(moved, vec![ConfigFile::Repo, ConfigFile::Author]);
+        assert_eq!(fh.config_read(ConfigFile::Repo).unwrap(), "/home/ada/ideas");
+        assert_eq!(fh.config_read(ConfigFile::Editor).unwrap(), "nano");
+        assert_eq!(fh.config_read(ConfigFile::Author).unwrap(), "Ada");
+        assert!(fh.config_read(ConfigFile::Title).is_err());
+        assert!(!legacy.join("repo_path").exists());
+        assert!(!legacy.join(CONFIG_FILE_NAME).exists());
+        // Not moved, so not lost either
+        let editor = fs::read_to_string(legacy.join("editor_path")).unwrap();
+        assert_eq!(editor, "/usr/bin/vim\n");
+    }
+
+    #[test]
+    fn legacy_configs_stay_while_they_hold_unmoved_values() {
+        let dir = TempDir::new().unwrap();
+        let legacy = dir.path().join("legacy");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(
+            legacy.join(CONFIG_FILE_NAME),
+            "author = \"Ada\"\ntitle = \"Old ideas\"\n",
+        )
+        .unwrap();
+        let fh = FileHandler::new(dir.path().join("config")).with_legacy_root(&legacy);
+        fh.config_write(ConfigFile::Title, "Ideas".to_string())
+            .unwrap();
+
+        assert_eq!(
+            fh.config_migrate_legacy().unwrap(),
+            vec![ConfigFile::Author]
+        );
+        assert_eq!(fh.config_read(ConfigFile::Title).unwrap(), "Ideas");
+        assert!(legacy.join(CONFIG_FILE_NAME).is_file());
+    }
+
+    #[test]
+    fn legacy_files_stay_when_nothing_is_moved() {
+        let dir = TempDir::new().unwrap();
+        let legacy = dir.path().join("legacy");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("editor_path"), "/usr/bin/vim\n").unwrap();
+        let fh = FileHandler::new(dir.path().join("config")).with_legacy_root(&legacy);
+        fh.config_write(ConfigFile::Editor, "nano".to_string())
+            .unwrap();
+
+        assert!(fh.config_migrate_legacy().unwrap().is_empty());
+        assert_eq!(fh.config_read(ConfigFile::Editor).unwrap(), "nano");
+        assert!(legacy.join("editor_path").is_file());
+    }
+
+    #[test]
+    fn config_root_overrides_apply_in_order() {
+        let platform = || Some(PathBuf::from("/platform"));
+        let all = env(&[
+            (CONFIG_ROOT_ENV, "/sanctuary-home"),
+            ("XDG_CONFIG_HOME", "/xdg"),
+        ]);
+
+        let root = config_root_from(Some("/flag"), all, platform()).unwrap();
+        assert_eq!(root, PathBuf::from("/flag"));
+        let root = config_root_from(None, all, platform()).unwrap();
+        assert_eq!(root, PathBuf::from("/sanctuary-home"));
+        let xdg = env(&[(CONFIG_ROOT_ENV, ""), ("XDG_CONFIG_HOME", "/xdg")]);
+        let root = config_root_from(None, xdg, platform()).unwrap();
+        assert_eq!(root, PathBuf::from("/xdg/sanctuary"));
+        let relative_xdg = env(&[("XDG_CONFIG_HOME", "relative/config")]);
+        let root = config_root_from(None, relative_xdg, platform()).unwrap();
+        assert_eq!(root, PathBuf::from("/platform/sanctuary"));
+        assert!(config_root_from(None, env(&[]), None).is_err());
+    }
+
+    #[test]
+    fn overridden_config_roots_leave_the_home_alone() {
+        let dir = TempDir::new().unwrap();
+        let home = dir.path().join("home");
+        let legacy = home.join(LEGACY_DIR_NAME);
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("repo_path"), "/home/ada/ideas\n").unwrap();
+        let portable = dir.path().join("portable");
+        let portable = portable.to_str().unwrap();
+        let home_env = env(&[(CONFIG_ROOT_ENV, "/sanctuary-home")]);
+
+        let flag = FileHandler::from_lookup(Some(portable), env(&[]), None, Some(home.clone()));
+        let variable = FileHandler::from_lookup(None, home_env, None, Some(home.clone()));
+        for fh in &[flag.unwrap(), variable.unwrap()] {
+            assert_eq!(fh.legacy_root, None);
+            assert!(fh.config_migrate_legacy().unwrap().is_empty());
+        }
+        assert!(legacy.join("repo_path").is_file());
+
+        let xdg = env(&[("XDG_CONFIG_HOME", "/xdg")]);
+        let fh = FileHandler::from_lookup(None, xdg, None, Some(home)).unwrap();
+        assert_eq!(fh.legacy_root, Some(legacy));
     }
 }
@@ -0,0 +1,293 @@
+use super::{Commit, GitBackend, GitError, Pull, Upstream};
+use crate::error::{Error, Result};
+use git2::{
+    Cred, CredentialType, ErrorCode, FetchOptions, IndexAddOption, PushOptions, RemoteCallbacks,
+    Repository, StatusOptions,
+};
+use std::cell::RefCell;
+use std::path::Path;
+
+/// Works on repositories in-process through libgit2, without a git binary.
+pub struct Libgit2;
+
+impl GitBackend for Libgit2 {
+    fn is_repo(&self, repo_path: &str) -> bool {
+        Path::new(repo_path).join(".git").is_dir()
+    }
+
+    fn init(&self, repo_path: &str) -> Result<()> {
+        Repository::init(repo_path).map_err(failed("init"))?;
+        Ok(())
+    }
+
+    fn add_remote(&self, repo_path: &str, name: &str, url: &str) -> Result<()> {
+        open(repo_path)?
+            .remote(name, url)
+            .map_err(failed("remote"))?;
+        Ok(())
+    }
+
+    fn current_branch(&self, repo_path: &str) -> Result<String> {
+        let repo = open(repo_path)?;
+        let head = repo
+            .find_reference("HEAD")
+            .map_err(failed("symbolic-ref"))?;
+        match head.symbolic_target() {
+            Some(target) => Ok(target.trim_start_matches("refs/heads/").to_string()),
+            None => Err(Error::Git(GitError {
+                command: vec!["symbolic-ref".to_string()],
+                code: None,
+                stdout: String::new(),
+                stderr: "HEAD is not on a branch".to_string(),
+            })),
+        }
+    }
+
+    fn tracked_upstream(&self, repo_path: &str) -> Result<(Option<String>, Option<String>)> {
+        let current = self.current_branch(repo_path)?;
+        let config = open(repo_path)?.config().map_err(failed("config"))?;
+        let remote = config_value(&config, &format!("branch.{}.remote", current))?;
+        let branch = config_value(&config, &format!("branch.{}.merge", current))?
+            .map(|merge| merge.trim_start_matches("refs/heads/").to_string());
+        Ok((remote, branch))
+    }
+
+    fn stage(&self, repo_path: &str, paths: &[&str]) -> Result<()> {
+        let repo = open(repo_path)?;
+        let specs: Vec<String> = paths
+            .iter()
+            .map(|path| {
+                let path = Path::new(path);
+                path.strip_prefix(repo_path)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        let mut index = repo.index().map_err(failed("add"))?;
+        index
+            .add_all(specs.iter(), IndexAddOption::DEFAULT, None)
+            .map_err(failed("add"))?;
+        // add_all skips files that were deleted from the working tree
+        index
+            .update_all(specs.iter(), None)
+            .map_err(failed("add"))?;
+        index.write().map_err(failed("add"))
+    }
+
+    fn commit(&self, repo_path: &str, subject: &str) -> Result<()> {
+        let repo = open(repo_path)?;
+        let signature = repo.signature().map_err(failed("commit"))?;
+        let tree_id = repo
+            .index()
+            .and_then(|mut index| index.write_tree())
+            .map_err(failed("commit"))?;
+        let tree = repo.find_tree(tree_id).map_err(failed("commit"))?;
+        let parent = match repo.head() {
+            Ok(head) => Some(head.peel_to_commit().map_err(failed("commit"))?),
+            Err(ref e)
+                if e.code() == ErrorCode::UnbornBranch || e.code() == ErrorCode::NotFound =>
+            {
+                None
+            }
+            Err(e) => return Err(failed("commit")(e)),
+        };
+
+        // Like git, refuse to record a commit that changes nothing
+        if parent.as_ref().map(|parent| parent.tree_id()) == Some(tree_id) {
+            return Err(Error::Git(GitError {
+                command: vec!["commit".to_string()],
+                code: Some(1),
+                stdout: "nothing to commit, working tree clean".to_string(),
+                stderr: String::new(),
+            }));
+        }
+
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            subject,
+            &tree,
+            &parents,
+        )
+        .map_err(failed("commit"))?;
+        Ok(())
+    }
+
+    fn push(&self, repo_path: &str, upstream: &Upstream) -> Result<()> {
+        let repo = open(repo_path)?;
+        let mut remote = repo.find_remote(&upstream.remote).map_err(failed("push"))?;
+
+        // The server reports rejected refs through this callback, not as an error
+        let rejected = RefCell::new(None);
+        let mut callbacks = callbacks();
+        callbacks.push_update_reference(|refname, status| {
+            if let Some(status) = status {
+                *rejected.borrow_mut() = Some(format!("{} rejected: {}", refname, status));
+            }
+            Ok(())
+        });
+        let mut options = PushOptions::new();
+        options.remote_callbacks(callbacks);
+
+        let refspec = format!("HEAD:refs/heads/{}", upstream.branch);
+        remote
+            .push(&[refspec.as_str()], Some(&mut options))
+            .map_err(failed("push"))?;
+
+        let rejected = rejected.borrow().clone();
+        match rejected {
+            Some(reason) => Err(Error::Git(GitError {
+                command: vec!["push".to_string()],
+                code: None,
+                stdout: String::new(),
+                stderr: reason,
+            })),
+            None => Ok(()),
+        }
+    }
+
+    fn pull(&self, repo_path: &str, upstream: &Upstream) -> Result<Pull> {
+        let repo = open(repo_path)?;
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(callbacks());
+        repo.find_remote(&upstream.remote)
+            .and_then(|mut remote| remote.fetch(&[] as &[&str], Some(&mut options), None))
+            .map_err(failed("fetch"))?;
+
+        let head = repo.head().map_err(failed("rebase"))?;
+        let head_id = head.peel_to_commit().map_err(failed("rebase"))?.id();
+        let remote_ref = format!("refs/remotes/{}/{}", upstream.remote, upstream.branch);
+        let remote_id = match repo.refname_to_id(&remote_ref) {
+            Ok(remote_id) => remote_id,
+            // Nothing has been pushed to this branch yet
+            Err(ref e) if e.code() == ErrorCode::NotFound => {
+                let mut walk = repo.revwalk().map_err(failed("rev-list"))?;
+                walk.push(head_id).map_err(failed("rev-list"))?;
+                return Ok(Pull {
+                    incoming: 0,
+                    outgoing: walk.count(),
+                });
+            }
+            Err(e) => return Err(failed("rev-parse")(e)),
+        };
+
+        let (outgoing, incoming) = repo
+            .graph_ahead_behind(head_id, remote_id)
+            .map_err(failed("rev-list"))?;
+        if incoming > 0 {
+            rebase(&repo, &head, remote_id)?;
+        }
+        Ok(Pull { incoming, outgoing })
+    }
+
+    fn log(&self, repo_path: &str, limit: usize) -> Result<Vec<Commit>> {
+        let repo = open(repo_path)?;
+        let mut walk = repo.revwalk().map_err(failed("log"))?;
+        walk.push_head().map_err(failed("log"))?;
+        walk.take(limit)
+            .map(|id| {
+                let commit = id
+                    .and_then(|id| repo.find_commit(id))
+                    .map_err(failed("log"))?;
+                Ok(Commit {
+                    id: commit.id().to_string(),
+                    summary: commit.summary().unwrap_or_default().to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn status(&self, repo_path: &str) -> Result<Vec<String>> {
+        let repo = open(repo_path)?;
+        let mut options = StatusOptions::new();
+        options.include_untracked(true).recurse_untracked_dirs(true);
+        let statuses = repo
+            .statuses(Some(&mut options))
+            .map_err(failed("status"))?;
+        Ok(statuses
+            .iter()
+            .filter_map(|entry| entry.path().map(str::to_string))
+            .collect())
+    }
+}
+
+/// Replays the commits of `head` onto `onto`, aborting on the first conflict.
+fn rebase(repo: &Repository, head: &git2::Reference, onto: git2::Oid) -> Result<()> {
+    let signature = repo.signature().map_err(failed("rebase"))?;
+    let branch = repo
+        .reference_to_annotated_commit(head)
+        .map_err(failed("rebase"))?;
+    let onto = repo.find_annotated_commit(onto).map_err(failed("rebase"))?;
+    let mut rebase = repo
+        .rebase(Some(&branch), Some(&onto), None, None)
+        .map_err(failed("rebase"))?;
+
+    while let Some(operation) = rebase.next() {
+        operation.map_err(failed("rebase"))?;
+        let index = repo.index().map_err(failed("rebase"))?;
+        if index.has_conflicts() {
+            let conflicts = index
+                .conflicts()
+                .map_err(failed("rebase"))?
+                .filter_map(|conflict| conflict.ok())
+                .filter_map(|conflict| conflict.our.or(conflict.their).or(conflict.ancestor))
+                .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+                .collect();
+            rebase.abort().map_err(failed("rebase"))?;
+            return Err(Error::SyncConflict(conflicts));
+        }
+        match rebase.commit(None, &signature, None) {
+            // The remote already has this change
+            Err(ref e) if e.code() == ErrorCode::Applied => {}
+            other => {
+                other.map_err(failed("rebase"))?;
+            }
+        }
+    }
+    rebase.finish(Some(&signature)).map_err(failed("rebase"))
+}
+
+fn callbacks<'a>() -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|url, username, allowed| {
+        if allowed.contains(CredentialType::SSH_KEY) {
+            if let Some(username) = username {
+                return Cred::ssh_key_from_agent(username);
+            }
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            if let Ok(config) = git2::Config::open_default() {
+                return Cred::credential_helper(&config, url, username);
+            }
+        }
+        Cred::default()
+    });
+    callbacks
+}
+
+fn config_value(config: &git2::Config, key: &str) -> Result<Option<String>> {
+    match config.get_string(key) {
+        Ok(value) => Ok(Some(value)),
+        Err(ref e) if e.code() == ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(failed("config")(e)),
+    }
+}
+
+fn open(repo_path: &str) -> Result<Repository> {
+    Repository::open(repo_path).map_err(failed("open"))
+}
+
+/// Wraps a libgit2 error in the same `GitError` the shell backend reports.
+fn failed(command: &'static str) -> impl Fn(git2::Error) -> Error {
+    move |e| {
+        Error::Git(GitError {
+            command: vec![command.to_string()],
+            code: Some(e.raw_code()),
+            stdout: String::new(),
+            stderr: e.message().to_string(),
+        })
+    }
+}
@@ -0,0 +1,348 @@
+//! Version control for idea books, behind the `GitBackend` trait so the git
+//! binary, libgit2 or a test double can be plugged into `IdeaBook`.
+
+use crate::error::Result;
+use std::{error, fmt};
+
+#[cfg(feature = "libgit2")]
+mod libgit2;
+mod recording;
+mod shell;
+
+#[cfg(feature = "libgit2")]
+pub use self::libgit2::Libgit2;
+pub use self::recording::{GitCall, RecordingBackend};
+pub use self::shell::Shell;
+
+/// A git operation that could not be run or finished unsuccessfully.
+#[derive(Debug)]
+pub struct GitError {
+    /// Arguments passed to git, without the repository selection flags.
+    pub command: Vec<String>,
+    /// Exit code of the process, `None` if it never ran or was killed by a signal.
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl GitError {
+    /// The most useful thing git said about the failure. Some commands, like
+    /// `commit` with nothing staged, explain themselves on stdout.
+    pub fn message(&self) -> &str {
+        if self.stderr.trim().is_empty() {
+            self.stdout.trim()
+        } else {
+            self.stderr.trim()
+        }
+    }
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let subcommand = self.command.first().map(String::as_str).unwrap_or("git");
+        match self.code {
+            Some(code) => write!(
+                f,
+                "{} failed (exit code {}): {}",
+                subcommand,
+                code,
+                self.message()
+            ),
+            None => write!(f, "{} failed: {}", subcommand, self.message()),
+        }
+    }
+}
+
+impl error::Error for GitError {}
+
+/// Where ideas get pushed to and pulled from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upstream {
+    pub remote: String,
+    pub branch: String,
+}
+
+/// What a successful `sync` did.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SyncReport {
+    /// Whether uncommitted changes were committed before syncing.
+    pub committed: bool,
+    /// Commits fetched from the remote and rebased onto.
+    pub pulled: usize,
+    /// Local commits pushed to the remote.
+    pub pushed: usize,
+}
+
+/// How the local branch relates to the remote one after a `pull`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Pull {
+    /// Remote commits the local ones were rebased onto.
+    pub incoming: usize,
+    /// Local commits the remote does not have yet.
+    pub outgoing: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Commit {
+    pub id: String,
+    pub summary: String,
+}
+
+/// Version control operations `IdeaBook` needs. Every method takes the
+/// repository it works on, so one backend can serve several books.
+pub trait GitBackend {
+    /// Whether `repo_path` has its own `.git` directory.
+    fn is_repo(&self, repo_path: &str) -> bool;
+    fn init(&self, repo_path: &str) -> Result<()>;
+    fn add_remote(&self, repo_path: &str, name: &str, url: &str) -> Result<()>;
+    fn current_branch(&self, repo_path: &str) -> Result<String>;
+    /// The remote and branch the current branch tracks, if any.
+    fn tracked_upstream(&self, repo_path: &str) -> Result<(Option<String>, Option<String>)>;
+    /// Stages additions, modifications and removals of `paths`.
+    fn stage(&self, repo_path: &str, paths: &[&str]) -> Result<()>;
+    fn commit(&self, repo_path: &str, subject: &str) -> Result<()>;
+    fn push(&self, repo_path: &str, upstream: &Upstream) -> Result<()>;
+    /// Fetches `upstream` and rebases local commits onto it. A conflicting
+    /// rebase is aborted, leaving the repository as it was, and the
+    /// conflicting files are returned in `Error::SyncConflict`.
+    fn pull(&self, repo_path: &str, upstream: &Upstream) -> Result<Pull>;
+    /// The most recent `limit` commits on the current branch, newest first.
+    fn log(&self, repo_path: &str, limit: usize) -> Result<Vec<Commit>>;
+    /// Paths with uncommitted changes, including untracked files.
+    fn status(&self, repo_path: &str) -> Result<Vec<String>>;
+
+    /// Resolves the remote and branch to sync with. Explicitly configured
+    /// values win, anything left unset comes from the current branch's
+    /// upstream, falling back to `origin` and the current branch when no
+    /// upstream is tracked.
+    fn upstream(
+        &self,
+        repo_path: &str,
+        remote: Option<&str>,
+        branch: Option<&str>,
+    ) -> Result<Upstream> {
+        if let (Some(remote), Some(branch)) = (remote, branch) {
+            return Ok(Upstream {
+                remote: remote.to_string(),
+                branch: branch.to_string(),
+            });
+        }
+
+        let (tracked_remote, tracked_branch) = self.tracked_upstream(repo_path)?;
+        Ok(Upstream {
+            remote: remote
+                .map(str::to_string)
+                .or(tracked_remote)
+                .unwrap_or_else(|| "origin".to_string()),
+            branch: match branch.map(str::to_string).or(tracked_branch) {
+                Some(branch) => branch,
+                None => self.current_branch(repo_path)?,
+            },
+        })
+    }
+
+    fn add_and_commit(&self, repo_path: &str, filename: &str, subject: &str) -> Result<()> {
+        self.stage(repo_path, &[filename])?;
+        self.commit(repo_path, subject)
+    }
+
+    /// Stages `filenames` and records them as the repository's first commit.
+    fn initial_commit(&self, repo_path: &str, filenames: &[&str]) -> Result<()> {
+        self.stage(repo_path, filenames)?;
+        self.commit(repo_path, "Initial commit")
+    }
+
+    /// Commits any pending changes, rebases local commits onto `upstream`
+    /// and pushes the result.
+    fn sync(&self, repo_path: &str, upstream: &Upstream) -> Result<SyncReport> {
+        let mut report = SyncReport::default();
+
+        let pending = self.status(repo_path)?;
+        if !pending.is_empty() {
+            let paths: Vec<&str> = pending.iter().map(String::as_str).collect();
+            self.stage(repo_path, &paths)?;
+            self.commit(repo_path, "Sync ideas")?;
+            report.committed = true;
+        }
+
+        let pull = self.pull(repo_path, upstream)?;
+        report.pulled = pull.incoming;
+        report.pushed = pull.outgoing;
+        if report.pushed > 0 {
+            self.push(repo_path, upstream)?;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    /// Every backend that talks to a real repository.
+    fn backends() -> Vec<Box<dyn GitBackend>> {
+        vec![
+            Box::new(Shell),
+            #[cfg(feature = "libgit2")]
+            Box::new(Libgit2),
+        ]
+    }
+
+    fn git_in(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .current_dir(dir)
+            .args(args)
+            .output()
+            .expect("git must be installed to run these tests")
+            .status;
+        assert!(
+            status.success(),
+            "git {:?} failed in {}",
+            args,
+            dir.display()
+        );
+    }
+
+    fn clone(remote: &Path, dir: &Path) -> String {
+        git_in(
+            dir.parent().unwrap(),
+            &[
+                "clone",
+                "-q",
+                remote.to_str().unwrap(),
+                dir.to_str().unwrap(),
+            ],
+        );
+        git_in(dir, &["config", "user.name", "Sanctuary Test"]);
+        git_in(dir, &["config", "user.email", "test@sanctuary.invalid"]);
+        dir.to_str().unwrap().to_string()
+    }
+
+    fn write(repo: &str, file: &str, contents: &str) {
+        let path = Path::new(repo).join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn main_branch() -> Upstream {
+        Upstream {
+            remote: "origin".to_string(),
+            branch: "main".to_string(),
+        }
+    }
+
+    /// A bare remote plus a clone that already pushed one chapter to it.
+    fn setup(git: &dyn GitBackend) -> (TempDir, PathBuf, String) {
+        let tmp = TempDir::new().unwrap();
+        let remote = tmp.path().join("remote.git");
+        fs::create_dir(&remote).unwrap();
+        git_in(&remote, &["init", "-q", "--bare", "--initial-branch=main"]);
+        let first = clone(&remote, &tmp.path().join("first"));
+        write(
+            &first,
+            "src/SUMMARY.md",
+            "# Summary\n\n- [Idea](./idea.md)\n",
+        );
+        write(&first, "src/idea.md", "# Idea\n");
+        git.sync(&first, &main_branch()).unwrap();
+        (tmp, remote, first)
+    }
+
+    #[test]
+    fn sync_pushes_local_ideas_and_pulls_remote_ones() {
+        for git in backends() {
+            let (tmp, remote, first) = setup(&*git);
+            let second = clone(&remote, &tmp.path().join("second"));
+
+            write(&second, "src/other.md", "# Other\n");
+            let report = git.sync(&second, &main_branch()).unwrap();
+            assert_eq!(
+                report,
+                SyncReport {
+                    committed: true,
+                    pulled: 0,
+                    pushed: 1,
+                }
+            );
+
+            write(&first, "src/idea.md", "# Idea\n\nMore detail\n");
+            let report = git.sync(&first, &main_branch()).unwrap();
+            assert_eq!(report.pulled, 1);
+            assert_eq!(report.pushed, 1);
+            assert!(Path::new(&first).join("src/other.md").exists());
+            assert_eq!(git.log(&first, 1).unwrap()[0].summary, "Sync ideas");
+            assert!(git.status(&first).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn sync_aborts_and_reports_conflicting_chapters() {
+        for git in backends() {
+            let (tmp, remote, first) = setup(&*git);
+            let second = clone(&remote, &tmp.path().join("second"));
+
+            write(
+                &second,
+                "src/idea.md",
+                "# Idea\n\nFrom the second machine\n",
+            );
+            git.sync(&second, &main_branch()).unwrap();
+
+            write(&first, "src/idea.md", "# Idea\n\nFrom the first machine\n");
+            match git.sync(&first, &main_branch()) {
+                Err(Error::SyncConflict(paths)) => assert_eq!(paths, vec!["src/idea.md"]),
+                other => panic!("expected a conflict, got {:?}", other),
+            }
+
+            assert!(!Path::new(&first).join(".git/rebase-merge").exists());
+            assert!(!Path::new(&first).join(".git/rebase-apply").exists());
+            let contents = fs::read_to_string(Path::new(&first).join("src/idea.md")).unwrap();
+            assert_eq!(contents, "# Idea\n\nFrom the first machine\n");
+        }
+    }
+
+    #[test]
+    fn upstream_defaults_to_the_tracked_branch() {
+        for git in backends() {
+            let (tmp, remote, _first) = setup(&*git);
+            let second = clone(&remote, &tmp.path().join("second"));
+
+            assert_eq!(git.upstream(&second, None, None).unwrap(), main_branch());
+            assert_eq!(
+                git.upstream(&second, Some("backup"), None).unwrap(),
+                Upstream {
+                    remote: "backup".to_string(),
+                    branch: "main".to_string(),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn recording_backend_logs_calls_instead_of_running_git() {
+        let mut git = RecordingBackend::new();
+        git.pending = vec!["src/idea.md".to_string()];
+        git.pull = Pull {
+            incoming: 0,
+            outgoing: 1,
+        };
+        let report = git.sync("/nowhere", &main_branch()).unwrap();
+
+        assert!(report.committed);
+        assert_eq!(
+            git.calls(),
+            vec![
+                GitCall::Stage(vec!["src/idea.md".to_string()]),
+                GitCall::Commit("Sync ideas".to_string()),
+                GitCall::Pull(main_branch()),
+                GitCall::Push(main_branch()),
+            ]
+        );
+        assert_eq!(git.log("/nowhere", 5).unwrap()[0].summary, "Sync ideas");
+    }
+}
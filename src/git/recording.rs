@@ -0,0 +1,124 @@
+use super::{Commit, GitBackend, Pull, Upstream};
+use crate::error::{Error, Result};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A call made to a `RecordingBackend`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GitCall {
+    Init(String),
+    AddRemote { name: String, url: String },
+    Stage(Vec<String>),
+    Commit(String),
+    Push(Upstream),
+    Pull(Upstream),
+}
+
+/// In-memory `GitBackend` that records every mutating call instead of
+/// touching a repository, so `IdeaBook` flows can be tested without git.
+/// Clones share the same call log.
+#[derive(Debug, Default, Clone)]
+pub struct RecordingBackend {
+    calls: Rc<RefCell<Vec<GitCall>>>,
+    /// Whether `is_repo` reports an existing repository.
+    pub has_repo: bool,
+    /// Paths `status` reports as changed.
+    pub pending: Vec<String>,
+    /// What `pull` reports when there are no `conflicts`.
+    pub pull: Pull,
+    /// Paths `pull` fails with as `Error::SyncConflict`, if any.
+    pub conflicts: Vec<String>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        RecordingBackend {
+            has_repo: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn calls(&self) -> Vec<GitCall> {
+        self.calls.borrow().clone()
+    }
+
+    fn record(&self, call: GitCall) {
+        self.calls.borrow_mut().push(call);
+    }
+}
+
+impl GitBackend for RecordingBackend {
+    fn is_repo(&self, _repo_path: &str) -> bool {
+        self.has_repo
+    }
+
+    fn init(&self, repo_path: &str) -> Result<()> {
+        self.record(GitCall::Init(repo_path.to_string()));
+        Ok(())
+    }
+
+    fn add_remote(&self, _repo_path: &str, name: &str, url: &str) -> Result<()> {
+        self.record(GitCall::AddRemote {
+            name: name.to_string(),
+            url: url.to_string(),
+        });
+        Ok(())
+    }
+
+    fn current_branch(&self, _repo_path: &str) -> Result<String> {
+        Ok("main".to_string())
+    }
+
+    fn tracked_upstream(&self, _repo_path: &str) -> Result<(Option<String>, Option<String>)> {
+        Ok((None, None))
+    }
+
+    fn stage(&self, _repo_path: &str, paths: &[&str]) -> Result<()> {
+        self.record(GitCall::Stage(
+            paths.iter().map(|path| path.to_string()).collect(),
+        ));
+        Ok(())
+    }
+
+    fn commit(&self, _repo_path: &str, subject: &str) -> Result<()> {
+        self.record(GitCall::Commit(subject.to_string()));
+        Ok(())
+    }
+
+    fn push(&self, _repo_path: &str, upstream: &Upstream) -> Result<()> {
+        self.record(GitCall::Push(upstream.clone()));
+        Ok(())
+    }
+
+    fn pull(&self, _repo_path: &str, upstream: &Upstream) -> Result<Pull> {
+        self.record(GitCall::Pull(upstream.clone()));
+        if self.conflicts.is_empty() {
+            Ok(self.pull)
+        } else {
+            Err(Error::SyncConflict(self.conflicts.clone()))
+        }
+    }
+
+    fn log(&self, _repo_path: &str, limit: usize) -> Result<Vec<Commit>> {
+        Ok(self
+            .calls
+            .borrow()
+            .iter()
+            .rev()
+            .filter_map(|call| match call {
+                GitCall::Commit(subject) => Some(subject.clone()),
+                _ => None,
+            })
+            .enumerate()
+            .map(|(id, summary)| Commit {
+                id: id.to_string(),
+                summary,
+            })
+            .take(limit)
+            .collect())
+    }
+
+    fn status(&self, _repo_path: &str) -> Result<Vec<String>> {
+        Ok(self.pending.clone())
+    }
+}
@@ -0,0 +1,192 @@
+use super::{Commit, GitBackend, GitError, Pull, Upstream};
+use crate::error::{Error, Result};
+use crate::utils;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Runs the `git` binary found on `PATH`.
+pub struct Shell;
+
+impl GitBackend for Shell {
+    fn is_repo(&self, repo_path: &str) -> bool {
+        Path::new(repo_path).join(".git").is_dir()
+    }
+
+    fn init(&self, repo_path: &str) -> Result<()> {
+        // There is no repository to point --git-dir at yet
+        execute(Command::new(git()?), &["init", "--quiet", repo_path])?;
+        Ok(())
+    }
+
+    fn add_remote(&self, repo_path: &str, name: &str, url: &str) -> Result<()> {
+        run(repo_path, &["remote", "add", name, url])?;
+        Ok(())
+    }
+
+    fn current_branch(&self, repo_path: &str) -> Result<String> {
+        Ok(run(repo_path, &["symbolic-ref", "--short", "HEAD"])?
+            .trim()
+            .to_string())
+    }
+
+    fn tracked_upstream(&self, repo_path: &str) -> Result<(Option<String>, Option<String>)> {
+        let current = self.current_branch(repo_path)?;
+        let remote = config_value(repo_path, &format!("branch.{}.remote", current))?;
+        let branch = config_value(repo_path, &format!("branch.{}.merge", current))?
+            .map(|merge| merge.trim_start_matches("refs/heads/").to_string());
+        Ok((remote, branch))
+    }
+
+    fn stage(&self, repo_path: &str, paths: &[&str]) -> Result<()> {
+        let mut args = vec!["add", "--all", "--"];
+        args.extend_from_slice(paths);
+        run(repo_path, &args)?;
+        Ok(())
+    }
+
+    fn commit(&self, repo_path: &str, subject: &str) -> Result<()> {
+        run(repo_path, &["commit", "-m", subject])?;
+        Ok(())
+    }
+
+    fn push(&self, repo_path: &str, upstream: &Upstream) -> Result<()> {
+        let refspec = format!("HEAD:{}", upstream.branch);
+        run(repo_path, &["push", &upstream.remote, &refspec])?;
+        Ok(())
+    }
+
+    fn pull(&self, repo_path: &str, upstream: &Upstream) -> Result<Pull> {
+        run(repo_path, &["fetch", &upstream.remote])?;
+        let remote_ref = format!("refs/remotes/{}/{}", upstream.remote, upstream.branch);
+        if run(
+            repo_path,
+            &["rev-parse", "--verify", "--quiet", &remote_ref],
+        )
+        .is_err()
+        {
+            // Nothing has been pushed to this branch yet
+            return Ok(Pull {
+                incoming: 0,
+                outgoing: count_commits(repo_path, "HEAD")?,
+            });
+        }
+
+        let pull = Pull {
+            incoming: count_commits(repo_path, &format!("HEAD..{}", remote_ref))?,
+            outgoing: count_commits(repo_path, &format!("{}..HEAD", remote_ref))?,
+        };
+        if pull.incoming > 0 {
+            if let Err(e) = run(repo_path, &["rebase", &remote_ref]) {
+                let conflicts = run(repo_path, &["diff", "--name-only", "--diff-filter=U"])?;
+                run(repo_path, &["rebase", "--abort"])?;
+                let conflicts: Vec<String> = conflicts.lines().map(str::to_string).collect();
+                return Err(if conflicts.is_empty() {
+                    e
+                } else {
+                    Error::SyncConflict(conflicts)
+                });
+            }
+        }
+        Ok(pull)
+    }
+
+    fn log(&self, repo_path: &str, limit: usize) -> Result<Vec<Commit>> {
+        let limit = format!("--max-count={}", limit);
+        let log = run(repo_path, &["log", &limit, "--format=%H%x09%s"])?;
+        Ok(log
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(2, '\t');
+                Some(Commit {
+                    id: parts.next()?.to_string(),
+                    summary: parts.next().unwrap_or_default().to_string(),
+                })
+            })
+            .collect())
+    }
+
+    fn status(&self, repo_path: &str) -> Result<Vec<String>> {
+        let status = run(
+            repo_path,
+            &["status", "--porcelain", "-z", "--untracked-files=all"],
+        )?;
+        let mut paths = Vec::new();
+        let mut entries = status.split('\0').filter(|entry| !entry.is_empty());
+        while let Some(entry) = entries.next() {
+            // Renames and copies are followed by the path they came from
+            if entry.starts_with('R') || entry.starts_with('C') {
+                if let Some(source) = entries.next() {
+                    paths.push(source.to_string());
+                }
+            }
+            paths.push(entry.get(3..).unwrap_or_default().to_string());
+        }
+        Ok(paths)
+    }
+}
+
+fn count_commits(repo_path: &str, range: &str) -> Result<usize> {
+    let count = run(repo_path, &["rev-list", "--count", range])?;
+    Ok(count.trim().parse().unwrap_or(0))
+}
+
+/// Reads a single git config value, `None` if it is not set.
+fn config_value(repo_path: &str, key: &str) -> Result<Option<String>> {
+    match run(repo_path, &["config", "--get", key]) {
+        Ok(value) => Ok(Some(value.trim().to_string())),
+        // `git config --get` exits with 1 when the key is missing
+        Err(Error::Git(GitError { code: Some(1), .. })) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Runs git against `repo_path`, returning its stdout if it exits successfully.
+fn run(repo_path: &str, args: &[&str]) -> Result<String> {
+    let mut git = Command::new(git()?);
+    git.args(default_args(repo_path).iter());
+    execute(git, args)
+}
+
+fn execute(mut git: Command, args: &[&str]) -> Result<String> {
+    let command: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let output = match git.args(args).output() {
+        Ok(output) => output,
+        Err(e) => {
+            return Err(Error::Git(GitError {
+                command,
+                code: None,
+                stdout: String::new(),
+                stderr: e.to_string(),
+            }))
+        }
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if output.status.success() {
+        Ok(stdout)
+    } else {
+        Err(Error::Git(GitError {
+            command,
+            code: output.status.code(),
+            stdout,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }))
+    }
+}
+
+fn git() -> Result<String> {
+    utils::get_if_available("git").ok_or_else(|| {
+        Error::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Cannot locate executable - git - on your system",
+        ))
+    })
+}
+
+fn default_args(repo_path: &str) -> [String; 2] {
+    [
+        format!("--git-dir={}/.git/", repo_path),
+        format!("--work-tree={}", repo_path),
+    ]
+}
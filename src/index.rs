@@ -0,0 +1,144 @@
+use crate::book_handler::Handler;
+use mdbook::book::{Chapter, SectionNumber};
+use mdbook::MDBook;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Identifies a chapter across renames and reordering: the path of its file
+/// relative to the source directory.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChapterId(PathBuf);
+
+impl ChapterId {
+    pub fn of(chapter: &Chapter) -> Self {
+        ChapterId::from(chapter.path.as_path())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl From<&Path> for ChapterId {
+    fn from(path: &Path) -> Self {
+        ChapterId(path.strip_prefix(".").unwrap_or(path).to_path_buf())
+    }
+}
+
+impl fmt::Display for ChapterId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.display())
+    }
+}
+
+/// Every chapter of a book in reading order, looked up by full section number
+/// or by `ChapterId`. Prefix and suffix chapters have no number and can only
+/// be found by ID.
+#[derive(Debug, Default, Clone)]
+pub struct ChapterIndex {
+    chapters: Vec<Chapter>,
+    by_number: HashMap<Vec<u32>, usize>,
+    by_id: HashMap<ChapterId, usize>,
+}
+
+impl ChapterIndex {
+    pub fn new(book: &MDBook) -> Self {
+        let mut index = ChapterIndex::default();
+        for chapter in book.get_chapters() {
+            let position = index.chapters.len();
+            if let Some(ref number) = chapter.number {
+                index.by_number.entry(number.0.clone()).or_insert(position);
+            }
+            index
+                .by_id
+                .entry(ChapterId::of(chapter))
+                .or_insert(position);
+            index.chapters.push(chapter.clone());
+        }
+        index
+    }
+
+    /// All chapters in reading order, sub-chapters right after their parent.
+    pub fn chapters(&self) -> &[Chapter] {
+        &self.chapters
+    }
+
+    pub fn by_number(&self, number: &SectionNumber) -> Option<&Chapter> {
+        self.by_number
+            .get(&number.0)
+            .map(|&position| &self.chapters[position])
+    }
+
+    pub fn by_id(&self, id: &ChapterId) -> Option<&Chapter> {
+        self.by_id.get(id).map(|&position| &self.chapters[position])
+    }
+
+    /// Looks a chapter up by section number, like `1.2` or `1.2.`, or by ID.
+    pub fn find(&self, query: &str) -> Option<&Chapter> {
+        let number: Option<Vec<u32>> = query
+            .trim_end_matches('.')
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect();
+        number
+            .and_then(|number| self.by_number(&SectionNumber(number)))
+            .or_else(|| self.by_id(&ChapterId::from(Path::new(query))))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chapters.is_empty()
+    }
+}
+
+/// How a chapter is listed when choosing one: indented by depth and
+/// prefixed with its section number.
+pub fn label(chapter: &Chapter) -> String {
+    format!("{}{}", "  ".repeat(chapter.parent_names.len()), chapter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn nested_and_unnumbered_chapters_are_all_indexed() {
+        let dir = TempDir::new().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("cooking")).unwrap();
+        for file in &["intro.md", "cooking.md", "cooking/bread.md", "travel.md"] {
+            fs::write(src.join(file), "").unwrap();
+        }
+        fs::write(
+            src.join("SUMMARY.md"),
+            "# Summary
+
+[Intro](./intro.md)
+
+- [Cooking](./cooking.md)
+    - [Bread](./cooking/bread.md)
+- [Travel](./travel.md)
+",
+        )
+        .unwrap();
+        let index = ChapterIndex::new(&MDBook::load(dir.path()).unwrap());
+
+        let names: Vec<&str> = index
+            .chapters()
+            .iter()
+            .map(|chap| chap.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Intro", "Cooking", "Bread", "Travel"]);
+        assert_eq!(index.find("1").unwrap().name, "Cooking");
+        assert_eq!(index.find("1.1.").unwrap().name, "Bread");
+        assert_eq!(index.find("2").unwrap().name, "Travel");
+        assert_eq!(index.find("./intro.md").unwrap().name, "Intro");
+        assert!(index.find("3").is_none());
+
+        let bread = index.find("cooking/bread.md").unwrap();
+        assert_eq!(ChapterId::of(bread).to_string(), "cooking/bread.md");
+        assert_eq!(label(bread), "  1.1. Bread");
+    }
+}
@@ -2,37 +2,61 @@ extern crate dialoguer;
 extern crate mdbook;
 extern crate termcolor;
 
-use dialoguer::Select;
 use termcolor::WriteColor;
 
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::io::{BufRead, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use crate::book_handler::Handler;
-use crate::file_handler::{ConfigManagement, FileHandler, FileManagement};
-use mdbook::{book::Chapter, config::Config, MDBook};
+use crate::file_handler::{ConfigManagement, FileHandler};
+use crate::git::GitBackend;
+use crate::index::ChapterIndex;
+use crate::library::Library;
+use mdbook::{book::Chapter, MDBook};
 use printer::{Print, Printer};
-use reader::{Read, Reader};
-use types::ConfigFile::{Author, Editor, Repo, Title};
-use utils::get_if_available;
+use prompt::{Choice, Prompter};
+use reader::Reader;
+use types::ConfigFile::{self, Author, Editor, Repo, Title};
+use types::{BookChoice, ParentChapter};
+use utils::{command_line, get_if_available};
+
+pub use error::{Error, Result};
 
 pub mod book_handler;
+mod error;
 pub mod file_handler;
-mod git;
+pub mod git;
+pub mod index;
 pub mod library;
 pub mod printer;
+pub mod prompt;
 pub mod reader;
+pub mod render;
+pub mod summary;
 pub mod types;
 pub mod utils;
 
+#[cfg(test)]
+mod tests;
+
+/// Looks up an environment variable, `None` when it is not set.
+pub type EnvLookup = dyn Fn(&str) -> Option<String>;
+
 pub struct IdeaBook<W, R> {
     pub fh: FileHandler,
     pub printer: Printer<W>,
     pub reader: Reader<R>,
-    pub map: HashMap<u32, Chapter>,
+    pub index: ChapterIndex,
+    pub git: Box<dyn GitBackend>,
+    pub prompter: Box<dyn Prompter>,
+    pub book: BookChoice,
+    /// Looks up environment variables such as `$EDITOR` and `$PAGER`.
+    pub env: Box<EnvLookup>,
 }
 
 impl<W, R> IdeaBook<W, R>
@@ -40,183 +64,310 @@ where
     W: Write + WriteColor,
     R: BufRead,
 {
-    pub fn run(&mut self) {
-        if self.is_config_missing() {
+    pub fn run(&mut self, parent: &ParentChapter) -> Result<()> {
+        if self.uses_library()? {
+            // Library books need no configured repo, but ideas still need an editor
+            if self.editor().is_err() {
+                self.setup_editor_path()?;
+            }
+        } else if self.is_config_missing() {
             if self.is_first_time_run() {
                 // If config dir is missing - create it
                 if !self.fh.config_dir_exists() {
-                    self.fh.config_dir_create().unwrap();
+                    self.fh.config_dir_create()?;
                 }
 
                 self.printer.print_fts_banner();
-                self.setup_repo_path().unwrap();
-                self.setup_editor_path().unwrap();
-                self.setup_author().unwrap();
-                self.setup_title().unwrap();
-                self.setup_book();
+                self.setup_repo_path()?;
+                if self.editor().is_err() {
+                    self.setup_editor_path()?;
+                }
+                self.setup_author()?;
+                self.setup_title()?;
+                self.setup_book()?;
             }
 
             // If repo path is missing - ask for it
             if self.fh.config_read(Repo).is_err() {
-                self.setup_repo_path().unwrap();
+                self.setup_repo_path()?;
             }
 
-            // If editor path is missing - ask for it
-            if self.fh.config_read(Editor).is_err() {
-                self.setup_editor_path().unwrap();
+            // If there is no editor to fall back on - ask for one
+            if self.editor().is_err() {
+                self.setup_editor_path()?;
             }
             if self.fh.config_read(Author).is_err() {
-                self.setup_author().unwrap();
+                self.setup_author()?;
             }
             if self.fh.config_read(Title).is_err() {
-                self.setup_title().unwrap();
+                self.setup_title()?;
             }
 
             self.printer
                 .print("First time setup complete. Happy ideation!");
-        } else {
-            self.mapthebook();
-            self.input_idea();
+            return Ok(());
+        }
+
+        self.mapthebook()?;
+        self.input_idea(parent)
+    }
+
+    pub fn migrate_config(&mut self) -> Result<()> {
+        let moved = self.fh.config_migrate_legacy()?;
+        if !moved.is_empty() {
+            let config_path = self.fh.config_file_path();
+            self.printer.print_migration_report(&config_path, &moved);
         }
+        Ok(())
     }
 
-    pub fn clear_repo(&self) {
+    pub fn clear_repo(&self) -> Result<()> {
         if self.fh.config_read(Repo).is_ok() {
-            self.fh
-                .file_rm(Repo)
-                .expect("Could not remove repo config file");
+            self.fh.config_remove(Repo)?;
         }
+        Ok(())
+    }
+    pub fn mapthebook(&mut self) -> Result<()> {
+        let book = self.open_book()?;
+        self.index = book.index_chapters();
+        Ok(())
     }
-    pub fn mapthebook(&mut self) {
-        let book = self.open_book();
-        self.map = book.index_chapters();
+    pub fn open_book(&mut self) -> Result<MDBook> {
+        let repo_path = self.repo_path()?;
+        MDBook::load(&repo_path).map_err(|source| Error::book(repo_path, source))
+    }
+
+    /// Where the book to work on lives: the library book chosen by name or
+    /// picked, else the library's default book, else the configured repo.
+    fn repo_path(&mut self) -> Result<String> {
+        if !self.uses_library()? {
+            return self.config(Repo);
+        }
+        let library = Library::load(&self.fh.library_file_path())?;
+        let member = match self.book {
+            BookChoice::Default => library.default_book(),
+            BookChoice::Named(ref book) => library.find(book),
+            BookChoice::Pick => {
+                let choices: Vec<Choice> = library
+                    .books()
+                    .iter()
+                    .map(|member| Choice {
+                        label: member.name().to_string(),
+                        keywords: member.path().display().to_string(),
+                        preview: member.path().display().to_string(),
+                    })
+                    .collect();
+                if choices.is_empty() {
+                    return Err(Error::BookNotFound("to choose from".to_string()));
+                }
+                let prompt = "Which book do you want to use?";
+                match self.prompter.fuzzy_select(prompt, &choices)? {
+                    Some(position) => Some(&library.books()[position]),
+                    None => return Err(Error::Cancelled),
+                }
+            }
+        };
+        let path = match (member, &self.book) {
+            (Some(member), _) => member.path().display().to_string(),
+            (None, BookChoice::Named(book)) => return Err(Error::BookNotFound(book.clone())),
+            (None, _) => return Err(Error::BookNotFound("set as the default".to_string())),
+        };
+        // Pick once, even if the book is opened again
+        self.book = BookChoice::Named(path.clone());
+        Ok(path)
+    }
+
+    /// Whether the book comes from the library rather than the configured
+    /// repo, which only serves when no book is asked for and there is no
+    /// default one.
+    fn uses_library(&self) -> Result<bool> {
+        Ok(match self.book {
+            BookChoice::Default => Library::load(&self.fh.library_file_path())?
+                .default_book()
+                .is_some(),
+            _ => true,
+        })
+    }
+
+    /// The remote and branch this book syncs with, as configured in its
+    /// `book.toml` or detected from git.
+    pub fn upstream(&mut self) -> Result<git::Upstream> {
+        let repo_path = self.repo_path()?;
+        let settings = self.open_book()?.book_settings()?;
+        self.git.upstream(
+            &repo_path,
+            settings.remote.as_deref(),
+            settings.branch.as_deref(),
+        )
+    }
+    /// Commits pending ideas, rebases them onto the remote and pushes them.
+    pub fn sync(&mut self) -> Result<()> {
+        let repo_path = self.repo_path()?;
+        let upstream = self.upstream()?;
+        match self.git.sync(&repo_path, &upstream) {
+            Ok(report) => {
+                self.printer.print_sync_report(&upstream, &report);
+                Ok(())
+            }
+            Err(Error::SyncConflict(paths)) => {
+                let book = self.open_book()?;
+                let src_dir = book.source_dir();
+                let chapters = book.get_chapters();
+                let conflicts = paths
+                    .into_iter()
+                    .map(|path| {
+                        let full_path = book.root.join(&path);
+                        let relative = full_path.strip_prefix(&src_dir).ok();
+                        match chapters
+                            .iter()
+                            .find(|chap| Some(chap.path.as_path()) == relative)
+                        {
+                            Some(chap) => format!("{} ({})", chap.name, path),
+                            None => path,
+                        }
+                    })
+                    .collect();
+                Err(Error::SyncConflict(conflicts))
+            }
+            Err(e) => Err(e),
+        }
     }
-    pub fn open_book(&self) -> MDBook {
-        match self.fh.config_read(Repo) {
-            Ok(repo_path) => return MDBook::load(repo_path).unwrap(),
-            Err(e) => panic!("No path to repository found: {}", e),
+    pub fn setup_book(&mut self) -> Result<()> {
+        let repo_path = self.config(Repo)?;
+        let author = self.config(Author)?;
+        let title = self.config(Title)?;
+        let book = MDBook::create_idea_book(PathBuf::from(&repo_path), title, author)?;
+        if !self.git.is_repo(&repo_path) {
+            self.setup_git(&repo_path, &book)?;
         }
+        Ok(())
     }
-    pub fn setup_book(&self) {
-        match self.fh.config_read(Repo) {
-            Ok(repo_path) => match self.fh.config_read(Author) {
-                Ok(author) => match self.fh.config_read(Title) {
-                    Ok(title) => {
-                        MDBook::create_idea_book(
-                            std::path::PathBuf::from(repo_path),
-                            title,
-                            author,
-                        )
-                        .unwrap();
-                    }
-                    Err(e) => panic!("{}", e),
-                },
-                Err(e) => panic!("{}", e),
-            },
-            Err(e) => panic!("{}", e),
+
+    fn setup_git(&mut self, repo_path: &str, book: &MDBook) -> Result<()> {
+        let question = format!("No git repository in {}, create one?", repo_path);
+        if !self.prompter.confirm(&question, true)? {
+            return Ok(());
+        }
+
+        self.git.init(repo_path)?;
+        let chapter_paths: Vec<String> = book
+            .get_chapter_paths()
+            .iter()
+            .map(|path| format!("src/{}", path.display()))
+            .collect();
+        let mut files = vec!["book.toml", "src/SUMMARY.md", ".gitignore"];
+        files.extend(chapter_paths.iter().map(String::as_str));
+        self.git.initial_commit(repo_path, &files)?;
+
+        let remote_url = self
+            .prompter
+            .input("Remote URL to push your ideas to (leave empty to skip)")?;
+        if !remote_url.is_empty() {
+            self.git.add_remote(repo_path, "origin", &remote_url)?;
         }
+        Ok(())
     }
 
-    pub fn read_chapter(&mut self, book: MDBook) {
-        self.printer.print_chapter_selection_header();
-        let select_index = Select::new()
-            .items(&book.get_chapter_names())
-            .default(0)
-            .interact()
-            .unwrap();
-        book.prettyprint_chapter(select_index as u32 + 1, &self.map);
+    /// Lets the user pick a chapter and shows it. `search_body` also
+    /// matches the search against the chapters' text.
+    pub fn read_chapter(&mut self, book: MDBook, search_body: bool) -> Result<()> {
+        let chapters = self.index.chapters().to_vec();
+        let prompt = "Which chapter do you want to see?";
+        let chapter = self.select_chapter(prompt, &book, &chapters, search_body)?;
+        let viewer = self.viewer()?;
+        book.prettyprint_chapter(&chapter, viewer.as_deref())
     }
 
     fn setup_author(&mut self) -> io::Result<()> {
         let mut author = String::new();
         while author.is_empty() {
-            self.printer.print_author_input_header();
-            self.printer.flush().unwrap();
-            author = self.reader.read();
+            author = self.prompter.input("What's your name?")?;
         }
         self.fh.config_write(Author, author)
     }
     fn setup_title(&mut self) -> io::Result<()> {
         let mut title = String::new();
         while title.is_empty() {
-            self.printer.print_title_input_header();
-            self.printer.flush().unwrap();
-            title = self.reader.read();
+            title = self.prompter.input("What's the title of your book?")?;
         }
         self.fh.config_write(Title, title)
     }
 
-    pub fn clear_editor(&self) {
+    pub fn clear_editor(&self) -> Result<()> {
         if self.fh.config_read(Editor).is_ok() {
-            self.fh
-                .file_rm(Editor)
-                .expect("Could not remove editor config file");
+            self.fh.config_remove(Editor)?;
         }
+        Ok(())
     }
-    pub fn open_idea_existing(&mut self) {
-        let repopath = self.fh.config_read(Repo).unwrap();
-        let book = self.open_book();
-        self.printer.print_chapter_selection_header();
-        let select_index = Select::new()
-            .items(&book.get_chapter_names())
-            .default(0)
-            .interact()
-            .unwrap();
-        let editorpath = self.fh.config_read(Editor).unwrap();
-        let idea_summary = "Test summary".to_string();
-        let chapter_path = book.get_chapter_path(select_index as u32 + 1, &self.map);
-        if let Ok(_) = self.open_editor(&editorpath, &chapter_path.to_str().unwrap()) {
-            self.add_idea_chapter(&chapter_path.to_str().unwrap(), idea_summary.clone());
-            book.add_chapter_to_summary(&repopath.clone(), &idea_summary);
-            git::add_and_commit(
-                &repopath,
-                chapter_path.to_str().unwrap(),
-                idea_summary.clone(),
-            )
-            .unwrap();
-        } else {
-            panic!("LOL");
+    pub fn open_idea_existing(&mut self, search_body: bool) -> Result<()> {
+        let repopath = self.repo_path()?;
+        let book = self.open_book()?;
+        let chapters = self.index.chapters().to_vec();
+        let prompt = "Which chapter do you want to edit?";
+        let chapter = self.select_chapter(prompt, &book, &chapters, search_body)?;
+        let editor = self.editor()?;
+        let chapter_path = book.get_chapter_path(&chapter);
+        // Start at the end of the chapter, where new thoughts go
+        let line = fs::read_to_string(&chapter_path)?.lines().count().max(1);
+        let before = content_hash(&chapter_path)?;
+        self.open_editor(&editor, &chapter_path.to_string_lossy(), line)?;
+        if content_hash(&chapter_path)? == before {
+            self.printer
+                .print(&format!("No changes to {}\n", chapter.name));
+            return Ok(());
         }
+        let subject = format!("Update {}", chapter.name);
+        self.git
+            .add_and_commit(&repopath, &chapter_path.to_string_lossy(), &subject)
     }
 
     fn setup_repo_path(&mut self) -> io::Result<()> {
         let mut input_repo_path = String::new();
 
         while input_repo_path.is_empty() {
-            self.printer
-                .print_input_header("Absolute path to your idea repo");
-            self.printer.flush().unwrap();
-            input_repo_path = self.reader.read();
+            input_repo_path = self.prompter.input("Absolute path to your idea repo")?;
         }
 
         self.fh.config_write(Repo, input_repo_path)
     }
 
-    fn setup_editor_path(&mut self) -> io::Result<()> {
-        self.printer.print_editor_selection_header();
-
-        let select_index = Select::new()
-            .default(0)
-            .items(&["vim", "nano", "Other (provide name, e.g. 'emacs')"])
-            .interact()
-            .unwrap();
+    fn setup_editor_path(&mut self) -> Result<()> {
+        let select_index = self.prompter.select(
+            "What editor do you want to use for writing down your ideas?",
+            &[
+                "vim".to_string(),
+                "nano".to_string(),
+                "Other (a command line, e.g. 'code --wait' or 'vim +{line} {file}')".to_string(),
+            ],
+            0,
+        )?;
 
         let chosen_editor = match select_index {
             0 => "vim".to_string(),
             1 => "nano".to_string(),
-            2 => {
-                self.printer.print_input_header("");
-                self.printer.flush().unwrap();
-                self.reader.read()
-            }
-            _ => panic!("You should not be able to get here"),
+            2 => self.prompter.input("Editor command line")?,
+            _ => return Err(Error::Cancelled),
         };
 
-        let editor_path = get_if_available(chosen_editor.as_str()).unwrap_or_else(|| {
-            panic!("Could not find executable for {} - aborting", chosen_editor)
-        });
+        let mut words = shell_words::split(&chosen_editor).map_err(|e| Error::Editor {
+            editor: chosen_editor.clone(),
+            reason: e.to_string(),
+        })?;
+        let editor_path = match words.first().and_then(|program| get_if_available(program)) {
+            Some(editor_path) => editor_path,
+            None => {
+                return Err(Error::Editor {
+                    editor: chosen_editor,
+                    reason: "could not find the executable".to_string(),
+                })
+            }
+        };
 
-        self.fh.config_write(Editor, editor_path)
+        // Keep the arguments, but pin the program to the one found now
+        words[0] = editor_path;
+        let editor = shell_words::join(&words);
+        self.fh.config_write(Editor, editor)?;
+        Ok(())
     }
 
     fn is_first_time_run(&self) -> bool {
@@ -226,47 +377,374 @@ where
             && self.fh.config_read(Title).is_err()
     }
 
-    fn is_config_missing(&self) -> bool {
+    pub fn is_config_missing(&self) -> bool {
         self.fh.config_read(Repo).is_err()
-            || self.fh.config_read(Editor).is_err()
+            || self.editor().is_err()
             || self.fh.config_read(Author).is_err()
             || self.fh.config_read(Title).is_err()
     }
-    fn add_idea_chapter(&mut self, path: &str, name: String) {
-        let mut book = self.open_book();
-        book.add_chapter(path, &name, None);
-    }
-    pub fn build_book(&mut self) {
-        let book = self.open_book();
-        book.build().unwrap();
-    }
-
-    fn input_idea(&mut self) {
-        self.printer.print_input_header(">> Idea summary");
-        let idea_summary = self.reader.read();
-        let book = self.open_book();
-        let editor_path = self.fh.config_read(Editor).unwrap();
-        let repo_path = self.fh.config_read(Repo).unwrap();
-        let chapter_path = format!("{}/src/{}.md", repo_path, idea_summary);
-        if let Ok(_) = self.open_editor(&editor_path, &chapter_path) {
-            self.add_idea_chapter(&chapter_path, idea_summary.clone());
-            book.add_chapter_to_summary(&repo_path.clone(), &idea_summary);
-            git::add_and_commit(&repo_path, &chapter_path, idea_summary.clone()).unwrap();
+    fn add_idea_chapter(
+        &mut self,
+        path: &str,
+        name: String,
+        parents: Option<Vec<String>>,
+    ) -> Result<()> {
+        let mut book = self.open_book()?;
+        book.add_chapter(path, &name, parents)
+    }
+    pub fn build_book(&mut self) -> Result<()> {
+        let book = self.open_book()?;
+        book.build()
+            .map_err(|source| Error::book(book.root.clone(), source))
+    }
+
+    fn input_idea(&mut self, parent: &ParentChapter) -> Result<()> {
+        let book = self.open_book()?;
+        let parent = self.resolve_parent(&book, parent)?;
+        let idea_summary = self.prompter.input("Idea summary")?;
+        self.capture(&book, parent, idea_summary, None)
+    }
+
+    /// Writes down an idea without prompting for it. `body` becomes the
+    /// chapter's text; without one the editor is opened as usual.
+    pub fn add(
+        &mut self,
+        summary: &str,
+        body: Option<String>,
+        parent: &ParentChapter,
+    ) -> Result<()> {
+        self.mapthebook()?;
+        let book = self.open_book()?;
+        let parent = self.resolve_parent(&book, parent)?;
+        self.capture(&book, parent, summary.to_string(), body)
+    }
+
+    fn resolve_parent(&mut self, book: &MDBook, parent: &ParentChapter) -> Result<Option<Chapter>> {
+        Ok(match parent {
+            ParentChapter::TopLevel => None,
+            ParentChapter::Named(name) => {
+                let chapter = book.find_chapter(name)?;
+                // Catch this before anything is written, as pick_parent does
+                if chapter.number.is_none() {
+                    return Err(Error::Summary {
+                        path: book.get_sum_path(),
+                        reason: format!(
+                            "{} is not a numbered chapter and cannot have sub-chapters",
+                            chapter.name
+                        ),
+                    });
+                }
+                Some(chapter.clone())
+            }
+            ParentChapter::Pick => Some(self.pick_parent(book)?),
+        })
+    }
+
+    /// Creates the chapter for a new idea from `body` or the editor, then
+    /// adds it to SUMMARY.md and commits it.
+    fn capture(
+        &mut self,
+        book: &MDBook,
+        parent: Option<Chapter>,
+        idea_summary: String,
+        body: Option<String>,
+    ) -> Result<()> {
+        let repo_path = self.repo_path()?;
+        let parent_path = parent.as_ref().map(|parent| parent.path.as_path());
+        let file_name = book.new_chapter_path(&idea_summary, parent_path)?;
+        let chapter_path = book.source_dir().join(&file_name);
+        let chapter_dir = chapter_path.parent().unwrap_or(&chapter_path);
+        // The top-most directory created here, so discarding leaves no empty parents
+        let new_dir = chapter_dir
+            .ancestors()
+            .take_while(|dir| !dir.exists())
+            .last()
+            .map(Path::to_path_buf);
+        fs::create_dir_all(chapter_dir)?;
+        let discard = || -> io::Result<()> {
+            if chapter_path.exists() {
+                fs::remove_file(&chapter_path)?;
+            }
+            if let Some(ref dir) = new_dir {
+                fs::remove_dir_all(dir)?;
+            }
+            Ok(())
+        };
+
+        let written = match body {
+            Some(mut body) => {
+                if !body.ends_with('\n') {
+                    body.push('\n');
+                }
+                fs::write(&chapter_path, body).map_err(Error::from)
+            }
+            None => self
+                .editor()
+                .and_then(|editor| self.open_editor(&editor, &chapter_path.to_string_lossy(), 1)),
+        };
+        if let Err(e) = written {
+            discard()?;
+            return Err(e);
+        }
+        let is_empty = fs::read_to_string(&chapter_path)
+            .map(|text| text.trim().is_empty())
+            .unwrap_or(true);
+        if is_empty {
+            discard()?;
+            self.printer.print(&format!(
+                "Nothing was written, discarded {}\n",
+                idea_summary
+            ));
+            return Ok(());
+        }
+
+        let parent_names = parent.as_ref().map(|parent| {
+            let mut names = parent.parent_names.clone();
+            names.push(parent.name.clone());
+            names
+        });
+        let added = self
+            .add_idea_chapter(
+                &chapter_path.to_string_lossy(),
+                idea_summary.clone(),
+                parent_names,
+            )
+            .and_then(|_| {
+                book.add_chapter_to_summary(
+                    &file_name.to_string_lossy(),
+                    &idea_summary,
+                    parent_path,
+                )
+            });
+        if let Err(e) = added {
+            discard()?;
+            return Err(e);
+        }
+
+        // The chapter is only reachable through its SUMMARY.md entry
+        let summary_path = book.get_sum_path();
+        let paths: Vec<String> = [chapter_path.as_path(), summary_path.as_path()]
+            .iter()
+            .map(|path| {
+                path.strip_prefix(&book.root)
+                    .unwrap_or(path)
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        self.git.stage(&repo_path, &paths)?;
+        self.git.commit(&repo_path, &idea_summary)
+    }
+
+    /// Asks which numbered chapter a new idea should be nested under.
+    fn pick_parent(&mut self, book: &MDBook) -> Result<Chapter> {
+        let chapters: Vec<Chapter> = self
+            .index
+            .chapters()
+            .iter()
+            .filter(|chap| chap.number.is_some())
+            .cloned()
+            .collect();
+        let prompt = "Which chapter should the new idea go under?";
+        self.select_chapter(prompt, book, &chapters, false)
+    }
+
+    /// Lets the user search `chapters` by title, section number and path,
+    /// and by their text too with `search_body`.
+    fn select_chapter(
+        &mut self,
+        prompt: &str,
+        book: &MDBook,
+        chapters: &[Chapter],
+        search_body: bool,
+    ) -> Result<Chapter> {
+        if chapters.is_empty() {
+            return Err(Error::ChapterNotFound("to choose from".to_string()));
+        }
+        let choices: Vec<Choice> = chapters
+            .iter()
+            .map(|chapter| {
+                // Unsaved chapters have no file yet
+                let text = fs::read_to_string(book.get_chapter_path(chapter)).unwrap_or_default();
+                let mut keywords = chapter.path.display().to_string();
+                if search_body {
+                    keywords.push('\n');
+                    keywords.push_str(&text);
+                }
+                Choice {
+                    label: index::label(chapter),
+                    keywords,
+                    preview: text
+                        .lines()
+                        .filter(|line| !line.trim().is_empty())
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                }
+            })
+            .collect();
+        match self.prompter.fuzzy_select(prompt, &choices)? {
+            Some(position) => Ok(chapters[position].clone()),
+            None => Err(Error::Cancelled),
+        }
+    }
+
+    /// Opens `file_path` at `line` with the `editor` command line.
+    fn open_editor(&self, editor: &str, file_path: &str, line: usize) -> Result<()> {
+        let line = line.to_string();
+        let args =
+            command_line(editor, &[("file", file_path), ("line", &line)]).map_err(|reason| {
+                Error::Editor {
+                    editor: editor.to_string(),
+                    reason,
+                }
+            })?;
+        match Command::new(&args[0]).args(&args[1..]).status() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(Error::Editor {
+                editor: editor.to_string(),
+                reason: format!("exited with {} while editing [{}]", status, file_path),
+            }),
+            Err(e) => Err(Error::Editor {
+                editor: editor.to_string(),
+                reason: format!("unable to open file [{}]: {}", file_path, e),
+            }),
+        }
+    }
+
+    /// The command line ideas are written with: the configured editor, then
+    /// `$VISUAL`, then `$EDITOR`.
+    fn editor(&self) -> Result<String> {
+        self.config(Editor).or_else(|missing| {
+            ["VISUAL", "EDITOR"]
+                .iter()
+                .filter_map(|name| (self.env)(name))
+                .find(|editor| !editor.trim().is_empty())
+                .ok_or(missing)
+        })
+    }
+
+    pub fn library_set_default(&mut self, book: &str) -> Result<()> {
+        let library_path = self.fh.library_file_path();
+        let mut library = Library::load(&library_path)?;
+        let name = library.set_default(book)?.name().to_string();
+        library.save(&library_path)?;
+        self.printer
+            .print(&format!("{} is now the default book\n", name));
+        Ok(())
+    }
+
+    /// Registers the mdBook at `path` in the library.
+    pub fn library_add(&mut self, path: &str) -> Result<()> {
+        let library_path = self.fh.library_file_path();
+        let mut library = Library::load(&library_path)?;
+        let name = library.add(path)?.name().to_string();
+        library.save(&library_path)?;
+        self.printer
+            .print(&format!("Added {} to the library\n", name));
+        Ok(())
+    }
+
+    /// Forgets a book, leaving its files where they are.
+    pub fn library_remove(&mut self, book: &str) -> Result<()> {
+        let library_path = self.fh.library_file_path();
+        let mut library = Library::load(&library_path)?;
+        let removed = library.remove(book)?;
+        library.save(&library_path)?;
+        self.printer
+            .print(&format!("Removed {} from the library\n", removed.name()));
+        Ok(())
+    }
+
+    /// Looks for books up to `depth` directories below `dir` and offers to
+    /// add the ones the library does not have yet.
+    pub fn library_scan(&mut self, dir: &str, depth: usize) -> Result<()> {
+        let library_path = self.fh.library_file_path();
+        let mut library = Library::load(&library_path)?;
+        let scan = library.scan(Path::new(dir), depth);
+        self.printer.print_scan_report(&scan);
+
+        let mut added = 0;
+        for member in scan.new {
+            let question = format!("Add {} at {}?", member.name(), member.path().display());
+            if !self.prompter.confirm(&question, true)? {
+                continue;
+            }
+            // Two books found in one scan may share a title
+            match library.insert(member) {
+                Ok(_) => added += 1,
+                Err(e) => self.printer.print_error(&e.to_string()),
+            }
+        }
+        if added > 0 {
+            library.save(&library_path)?;
+            self.printer
+                .print(&format!("Added {} book(s) to the library\n", added));
+        }
+        Ok(())
+    }
+
+    /// Rereads the chapters of every book and reports what changed.
+    pub fn library_refresh(&mut self) -> Result<()> {
+        let library_path = self.fh.library_file_path();
+        let mut library = Library::load(&library_path)?;
+        let drifts = library.refresh();
+        library.save(&library_path)?;
+        if drifts.is_empty() {
+            self.printer.print(&format!(
+                "All {} book(s) are up to date\n",
+                library.books().len()
+            ));
         } else {
-            panic!("LOL");
+            self.printer.print_drift_report(&drifts);
         }
+        Ok(())
     }
 
-    fn open_editor(&self, bin_path: &str, file_path: &str) -> io::Result<()> {
-        match Command::new(bin_path).arg(file_path).status() {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                eprintln!(
-                    "Error: Unable to open file [{}] with editor binary at [{}]: {}",
-                    file_path, bin_path, e
-                );
-                Err(e)
+    pub fn library_list(&mut self) -> Result<()> {
+        let library = Library::load(&self.fh.library_file_path())?;
+        self.printer.print_library(&library);
+        Ok(())
+    }
+
+    pub fn library_show(&mut self, book: &str) -> Result<()> {
+        let library = Library::load(&self.fh.library_file_path())?;
+        match library.find(book) {
+            Some(member) => {
+                self.printer.print_book(member);
+                Ok(())
             }
+            None => Err(Error::BookNotFound(book.to_string())),
+        }
+    }
+
+    /// The command line chapters are viewed with: the configured viewer, then
+    /// `$PAGER`. `None` means chapters are rendered in the terminal.
+    fn viewer(&self) -> Result<Option<String>> {
+        let configured = self.fh.config_load()?.viewer;
+        Ok(configured
+            .or_else(|| (self.env)("PAGER"))
+            .filter(|viewer| !viewer.trim().is_empty()))
+    }
+
+    /// Reads a setting, telling a missing value apart from a broken config file.
+    fn config(&self, config: ConfigFile) -> Result<String> {
+        self.fh.config_read(config).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => Error::ConfigMissing(config),
+            _ => Error::Io(e),
+        })
+    }
+}
+
+/// Fingerprints a file's contents, `None` when there is no such file.
+fn content_hash(path: &Path) -> io::Result<Option<u64>> {
+    match fs::read(path) {
+        Ok(contents) => {
+            let mut hasher = DefaultHasher::new();
+            contents.hash(&mut hasher);
+            Ok(Some(hasher.finish()))
         }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
     }
 }
@@ -1,20 +1,34 @@
-use mdbook::book::Book;
-use mdbook::book::BookItem;
-use mdbook::MDBook;
+use crate::error::{Error, Result};
+use crate::file_handler::write_atomic;
+use ignore::WalkBuilder;
+use mdbook::book::{parse_summary, BookItem, SummaryItem};
+use mdbook::{Config, MDBook};
 use serde::{Deserialize, Serialize};
-use serde_json::from_str;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::PathBuf;
+use serde_json::{from_str, Map, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Version of the library file format this build writes. Older files are
+/// migrated when loaded, newer ones are refused.
+pub const LIBRARY_VERSION: u64 = 1;
+
+/// Upgrades a library file from the version it is indexed by to the next.
+const MIGRATIONS: [fn(&mut Map<String, Value>); LIBRARY_VERSION as usize] = [v0_to_v1];
+
+/// The books sanctuary knows about, kept in a JSON file in the config
+/// directory.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Library {
+    version: u64,
     name: String,
     books: Vec<Member>,
+    /// Path of the book commands work on unless told otherwise.
+    default: Option<PathBuf>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A book in the library, with its chapters as of when it was added.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Member {
     name: String,
     path: PathBuf,
@@ -22,66 +36,589 @@ pub struct Member {
     count: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Part {
     name: String,
     path: PathBuf,
 }
 
+/// How a book on disk differs from what the library remembers of it.
+#[derive(Debug)]
+pub enum Drift {
+    /// The book's directory or `book.toml` is gone.
+    Vanished(Member),
+    /// The book's `SUMMARY.md` could not be read.
+    Unreadable { book: Member, error: Error },
+    /// The book's chapters changed since the last refresh, or some of their
+    /// files are missing.
+    Chapters {
+        book: Member,
+        added: Vec<Part>,
+        removed: Vec<Part>,
+        missing: Vec<Part>,
+    },
+}
+
+/// The books found under a directory by `Library::scan`.
+#[derive(Debug, Default)]
+pub struct Scan {
+    /// Books that are not in the library yet.
+    pub new: Vec<Member>,
+    /// Books the library already has.
+    pub known: Vec<PathBuf>,
+    /// Directories with a `book.toml` mdBook could not load.
+    pub failed: Vec<(PathBuf, Error)>,
+}
+
+impl Default for Library {
+    fn default() -> Self {
+        Library {
+            version: LIBRARY_VERSION,
+            name: String::new(),
+            books: Vec::new(),
+            default: None,
+        }
+    }
+}
+
 impl Library {
-    fn load(path: &str) -> Self
-    {
-        let mut contents = String::new();
-        let mut file = File::open(path).unwrap();
-        file.read_to_string(&mut contents).unwrap();
-        let res: Library = from_str(&contents).unwrap();
-        res
+    /// Reads the library at `path`, which is empty if there is no file yet.
+    /// Files written by older versions are migrated to the current format.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Library::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let invalid = |reason: &dyn std::fmt::Display| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unable to parse library at {}: {}", path, reason),
+            )
+        };
+
+        let mut value: Value = from_str(&contents).map_err(|e| invalid(&e))?;
+        let library = value
+            .as_object_mut()
+            .ok_or_else(|| invalid(&"expected a JSON object"))?;
+        // Files from before versioning have no version field
+        let version = match library.get("version") {
+            Some(version) => version
+                .as_u64()
+                .ok_or_else(|| invalid(&"the version is not a number"))?,
+            None => 0,
+        };
+        if version > LIBRARY_VERSION {
+            return Err(Error::LibraryVersion {
+                path: PathBuf::from(path),
+                version,
+            });
+        }
+        for migrate in &MIGRATIONS[version as usize..] {
+            migrate(library);
+        }
+        library.insert("version".to_string(), LIBRARY_VERSION.into());
+
+        let res: Library = serde_json::from_value(value).map_err(|e| invalid(&e))?;
+        Ok(res)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        Ok(write_atomic(path, &contents)?)
+    }
+
+    pub fn books(&self) -> &[Member] {
+        &self.books
+    }
+
+    pub fn default_book(&self) -> Option<&Member> {
+        let default = self.default.as_ref()?;
+        self.books.iter().find(|member| &member.path == default)
+    }
+
+    /// Makes the book with this name or path the default one.
+    pub fn set_default(&mut self, book: &str) -> Result<&Member> {
+        let path = match self.find(book) {
+            Some(member) => member.path.clone(),
+            None => return Err(Error::BookNotFound(book.to_string())),
+        };
+        self.default = Some(path);
+        Ok(self.default_book().unwrap())
+    }
+
+    /// Looks a book up by name, ignoring case, or by path.
+    pub fn find(&self, book: &str) -> Option<&Member> {
+        let path = fs::canonicalize(book).ok();
+        self.books.iter().find(|member| {
+            member.name.eq_ignore_ascii_case(book) || Some(&member.path) == path.as_ref()
+        })
+    }
+
+    /// Registers the mdBook at `path`, named after its title.
+    pub fn add<P: AsRef<Path>>(&mut self, path: P) -> Result<&Member> {
+        let member = Member::add(path.as_ref())?;
+        self.insert(member)
+    }
+
+    /// Registers a book found by `scan`.
+    pub fn insert(&mut self, member: Member) -> Result<&Member> {
+        if let Some(existing) = self
+            .books
+            .iter()
+            .find(|book| book.path == member.path || book.name.eq_ignore_ascii_case(&member.name))
+        {
+            return Err(Error::DuplicateBook {
+                name: existing.name.clone(),
+                path: existing.path.clone(),
+            });
+        }
+        self.books.push(member);
+        Ok(&self.books[self.books.len() - 1])
+    }
+
+    /// Looks for mdBooks in `root` and up to `depth` directories below it,
+    /// skipping hidden and git-ignored directories.
+    pub fn scan(&self, root: &Path, depth: usize) -> Scan {
+        let mut scan = Scan::default();
+        let walk = WalkBuilder::new(root)
+            .max_depth(Some(depth + 1))
+            .require_git(false)
+            .build();
+        for entry in walk.filter_map(|entry| entry.ok()) {
+            if entry.file_name() != "book.toml" {
+                continue;
+            }
+            let dir = match entry.path().parent() {
+                Some(dir) => dir,
+                None => continue,
+            };
+            let known = fs::canonicalize(dir)
+                .map(|dir| self.books.iter().any(|member| member.path == dir))
+                .unwrap_or(false);
+            if known {
+                scan.known.push(dir.to_path_buf());
+                continue;
+            }
+            match Member::add(dir) {
+                Ok(member) => scan.new.push(member),
+                Err(e) => scan.failed.push((dir.to_path_buf(), e)),
+            }
+        }
+        scan
+    }
+
+    /// Rereads every book's chapters from its `SUMMARY.md` and reports the
+    /// books that drifted. Vanished and unreadable books are left as they
+    /// were.
+    pub fn refresh(&mut self) -> Vec<Drift> {
+        let mut drifts = Vec::new();
+        for member in self.books.iter_mut() {
+            if !member.path.join("book.toml").is_file() {
+                drifts.push(Drift::Vanished(member.clone()));
+                continue;
+            }
+            let parts = match Member::read_parts(&member.path) {
+                Ok(parts) => parts,
+                Err(error) => {
+                    drifts.push(Drift::Unreadable {
+                        book: member.clone(),
+                        error,
+                    });
+                    continue;
+                }
+            };
+
+            let added: Vec<Part> = parts
+                .iter()
+                .filter(|part| !member.parts.iter().any(|old| old.path == part.path))
+                .cloned()
+                .collect();
+            let removed: Vec<Part> = member
+                .parts
+                .iter()
+                .filter(|old| !parts.iter().any(|part| part.path == old.path))
+                .cloned()
+                .collect();
+            let missing: Vec<Part> = parts
+                .iter()
+                .filter(|part| !part.path.is_file())
+                .cloned()
+                .collect();
+
+            member.count = parts.len() as u64;
+            member.parts = parts;
+            if !added.is_empty() || !removed.is_empty() || !missing.is_empty() {
+                drifts.push(Drift::Chapters {
+                    book: member.clone(),
+                    added,
+                    removed,
+                    missing,
+                });
+            }
+        }
+        drifts
+    }
+
+    /// Forgets a book, found by name or path. Its files are left alone.
+    pub fn remove(&mut self, book: &str) -> Result<Member> {
+        let found = self.find(book).map(|member| member.path.clone());
+        match found {
+            Some(path) => {
+                if self.default.as_ref() == Some(&path) {
+                    self.default = None;
+                }
+                let position = self.books.iter().position(|member| member.path == path);
+                Ok(self.books.remove(position.unwrap()))
+            }
+            None => Err(Error::BookNotFound(book.to_string())),
+        }
     }
 }
 
+/// Version 0 files predate versioning and the default book.
+fn v0_to_v1(library: &mut Map<String, Value>) {
+    library.entry("default").or_insert(Value::Null);
+}
+
 impl Member {
-    fn add<P>(path: P) -> Self
-    where
-        P: Into<PathBuf>,
-    {
-        let book = MDBook::load(path).unwrap();
-        let mut title = String::new();
-        if let Some(st) = book.config.book.title {
-            title = st;
+    fn add(path: &Path) -> Result<Self> {
+        if !path.join("book.toml").is_file() {
+            return Err(Error::NotABook(path.to_path_buf()));
         }
-        // This is probably the worst thing I ever wrote and probably ever will
-        // TODO Please fix this if I won't, there need to be checks for this
-        // Not really fixed but you can at least see how bad it is
-        title = book.root.parent().unwrap().file_name().unwrap().to_str().unwrap().to_owned();
-
-        let path = book.root;
-        let count = book.book.iter().count() as u64;
-        let parts = Member::collect_parts(&path, &book.book);
-        Member {
-            name: title,
+        let path = fs::canonicalize(path)?;
+        let book = MDBook::load(&path).map_err(|source| Error::book(path.clone(), source))?;
+
+        // Books without a title are known by their directory
+        let name = book
+            .config
+            .book
+            .title
+            .clone()
+            .filter(|title| !title.trim().is_empty())
+            .or_else(|| {
+                book.root
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| book.root.display().to_string());
+
+        let parts = Member::collect_parts(&book);
+        let count = parts.len() as u64;
+        Ok(Member {
+            name,
             path,
             parts,
             count,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// The chapters listed in the `SUMMARY.md` of the book at `path`. Unlike
+    /// loading the book, this neither needs nor creates the chapter files.
+    fn read_parts(path: &Path) -> Result<Vec<Part>> {
+        let config = Config::from_disk(path.join("book.toml"))
+            .map_err(|source| Error::book(path, source))?;
+        let srcpath = path.join(&config.book.src);
+        let summary = fs::read_to_string(srcpath.join("SUMMARY.md"))?;
+        let summary = parse_summary(&summary).map_err(|source| Error::book(path, source))?;
+
+        let mut parts = Vec::new();
+        let mut items: Vec<&SummaryItem> = summary
+            .prefix_chapters
+            .iter()
+            .chain(summary.numbered_chapters.iter())
+            .chain(summary.suffix_chapters.iter())
+            .rev()
+            .collect();
+        // Depth-first, in reading order
+        while let Some(item) = items.pop() {
+            if let SummaryItem::Link(link) = item {
+                let location = link.location.strip_prefix(".").unwrap_or(&link.location);
+                parts.push(Part {
+                    name: link.name.clone(),
+                    path: srcpath.join(location),
+                });
+                items.extend(link.nested_items.iter().rev());
+            }
         }
+        Ok(parts)
     }
-    fn collect_parts(rootpath: &PathBuf, book: &Book) -> Vec<Part> {
-        let mut res: Vec<Part> = Vec::new();
-        let mut srcpath = rootpath.clone();
-        srcpath.push("src/");
-        for items in book.iter() {
-            match items {
-                BookItem::Separator => {}
-                BookItem::Chapter(chap) => {
-                    let mut fullpath = srcpath.clone();
-                    fullpath.push(chap.path.clone());
-                    let part = Part {
-                        name: chap.name.clone(),
-                        path: fullpath,
-                    };
-                    res.push(part);
-                }
+
+    fn collect_parts(book: &MDBook) -> Vec<Part> {
+        let srcpath = book.source_dir();
+        book.book
+            .iter()
+            .filter_map(|item| match item {
+                BookItem::Separator => None,
+                BookItem::Chapter(chap) => Some(Part {
+                    name: chap.name.clone(),
+                    path: srcpath.join(&chap.path),
+                }),
+            })
+            .collect()
+    }
+}
+
+impl Part {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::book_handler::Handler;
+    use tempfile::TempDir;
+
+    fn create_book(dir: &Path, title: &str) -> PathBuf {
+        MDBook::create_idea_book(dir.to_path_buf(), title.to_string(), "Ada".to_string()).unwrap();
+        fs::canonicalize(dir).unwrap()
+    }
+
+    #[test]
+    fn books_are_added_by_title_and_saved() {
+        let dir = TempDir::new().unwrap();
+        let ideas = create_book(&dir.path().join("ideas"), "Kitchen ideas");
+        let library_path = dir.path().join("library.json");
+        let library_path = library_path.to_str().unwrap();
+
+        let mut library = Library::load(library_path).unwrap();
+        assert!(library.books().is_empty());
+        let member = library.add(&ideas).unwrap();
+        assert_eq!(member.name(), "Kitchen ideas");
+        assert_eq!(member.path(), ideas.as_path());
+        assert_eq!(member.parts()[0].path(), ideas.join("src/chapter_1.md"));
+        library.save(library_path).unwrap();
+
+        let mut library = Library::load(library_path).unwrap();
+        assert_eq!(library.find("kitchen ideas").unwrap().path(), ideas);
+        library.set_default("Kitchen ideas").unwrap();
+        assert_eq!(library.default_book().unwrap().path(), ideas);
+        assert_eq!(
+            library.remove(ideas.to_str().unwrap()).unwrap().name(),
+            "Kitchen ideas"
+        );
+        assert!(library.books().is_empty());
+        assert!(library.default_book().is_none());
+    }
+
+    #[test]
+    fn duplicates_and_other_directories_are_rejected() {
+        let dir = TempDir::new().unwrap();
+        let ideas = create_book(&dir.path().join("ideas"), "Ideas");
+        let copy = create_book(&dir.path().join("copy"), "ideas");
+        let mut library = Library::default();
+        library.add(&ideas).unwrap();
+
+        match library.add(&ideas) {
+            Err(Error::DuplicateBook { path, .. }) => assert_eq!(path, ideas),
+            other => panic!("expected a duplicate, got {:?}", other),
+        }
+        assert!(matches!(
+            library.add(&copy),
+            Err(Error::DuplicateBook { .. })
+        ));
+        assert!(matches!(library.add(dir.path()), Err(Error::NotABook(_))));
+        assert!(matches!(
+            library.remove("travel"),
+            Err(Error::BookNotFound(_))
+        ));
+        assert_eq!(library.books().len(), 1);
+    }
+
+    #[test]
+    fn scans_find_new_and_broken_books_within_reach() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let known = create_book(&root.join("known"), "Known");
+        let new = create_book(&root.join("notes/new"), "New");
+        create_book(&root.join("build/copy"), "Ignored");
+        create_book(&root.join("a/b/c/deep"), "Too deep");
+        fs::create_dir_all(root.join("broken")).unwrap();
+        fs::write(root.join("broken/book.toml"), "[book\n").unwrap();
+        fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        let mut library = Library::default();
+        library.add(&known).unwrap();
+
+        let scan = library.scan(root, 2);
+
+        let new_paths: Vec<&Path> = scan.new.iter().map(Member::path).collect();
+        assert_eq!(new_paths, vec![new.as_path()]);
+        assert_eq!(scan.known, vec![root.join("known")]);
+        assert_eq!(scan.failed.len(), 1);
+        assert_eq!(scan.failed[0].0, root.join("broken"));
+
+        library.insert(scan.new[0].clone()).unwrap();
+        assert!(library.scan(root, 2).new.is_empty());
+    }
+
+    #[test]
+    fn refreshing_reports_chapter_changes_and_vanished_books() {
+        let dir = TempDir::new().unwrap();
+        let kept = create_book(&dir.path().join("kept"), "Kept");
+        let gone = create_book(&dir.path().join("gone"), "Gone");
+        let mut library = Library::default();
+        library.add(&kept).unwrap();
+        library.add(&gone).unwrap();
+
+        fs::remove_dir_all(&gone).unwrap();
+        fs::write(kept.join("src/bread.md"), "").unwrap();
+        fs::write(
+            kept.join("src/SUMMARY.md"),
+            "# Summary\n\n- [Bread](./bread.md)\n    - [Rye](./rye.md)\n",
+        )
+        .unwrap();
+
+        let drifts = library.refresh();
+        assert_eq!(drifts.len(), 2);
+        match &drifts[0] {
+            Drift::Chapters {
+                book,
+                added,
+                removed,
+                missing,
+            } => {
+                assert_eq!(book.name(), "Kept");
+                let names = |parts: &[Part]| -> Vec<String> {
+                    parts.iter().map(|part| part.name().to_string()).collect()
+                };
+                assert_eq!(names(added), vec!["Bread", "Rye"]);
+                assert_eq!(names(removed), vec!["Chapter 1"]);
+                assert_eq!(names(missing), vec!["Rye"]);
+                assert!(!kept.join("src/rye.md").exists());
+            }
+            other => panic!("expected changed chapters, got {:?}", other),
+        }
+        assert!(matches!(&drifts[1], Drift::Vanished(book) if book.name() == "Gone"));
+
+        // Only what is still wrong is reported again
+        let drifts = library.refresh();
+        assert_eq!(drifts.len(), 2);
+        assert!(matches!(
+            &drifts[0],
+            Drift::Chapters { added, removed, missing, .. }
+                if added.is_empty() && removed.is_empty() && missing.len() == 1
+        ));
+        assert_eq!(library.find("kept").unwrap().parts().len(), 2);
+    }
+
+    #[test]
+    fn refreshing_an_unchanged_book_changes_nothing() {
+        let dir = TempDir::new().unwrap();
+        let ideas = create_book(&dir.path().join("ideas"), "Ideas");
+        fs::write(ideas.join("src/notes.md"), "").unwrap();
+        fs::write(
+            ideas.join("src/SUMMARY.md"),
+            "# Summary\n\n- [Chapter 1](./chapter_1.md)\n\n---\n\n[Notes](./notes.md)\n",
+        )
+        .unwrap();
+        let mut library = Library::default();
+        library.add(&ideas).unwrap();
+        let added = library.clone();
+
+        assert!(library.refresh().is_empty());
+        assert_eq!(library, added);
+    }
+
+    /// Writes `contents` as a library file and loads it.
+    fn load_str(dir: &TempDir, contents: &str) -> Result<Library> {
+        let path = dir.path().join("library.json");
+        fs::write(&path, contents).unwrap();
+        Library::load(path.to_str().unwrap())
+    }
+
+    /// Saves `library` and loads it back, checking nothing was lost.
+    fn round_trip(dir: &TempDir, library: &Library) -> String {
+        let path = dir.path().join("saved.json");
+        let path = path.to_str().unwrap();
+        library.save(path).unwrap();
+        assert_eq!(&Library::load(path).unwrap(), library);
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn version_0_files_are_migrated() {
+        let dir = TempDir::new().unwrap();
+        let library = load_str(
+            &dir,
+            r#"{
+                "name": "",
+                "books": [{
+                    "name": "Ideas",
+                    "path": "/books/ideas",
+                    "parts": [{"name": "Chapter 1", "path": "/books/ideas/src/chapter_1.md"}],
+                    "count": 1
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(library.version, LIBRARY_VERSION);
+        assert_eq!(library.books()[0].name(), "Ideas");
+        assert_eq!(library.books()[0].parts()[0].name(), "Chapter 1");
+        assert!(library.default_book().is_none());
+        let saved = round_trip(&dir, &library);
+        assert!(saved.contains(&format!("\"version\": {}", LIBRARY_VERSION)));
+    }
+
+    #[test]
+    fn version_1_files_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let library = load_str(
+            &dir,
+            r#"{
+                "version": 1,
+                "name": "",
+                "books": [{
+                    "name": "Ideas",
+                    "path": "/books/ideas",
+                    "parts": [],
+                    "count": 0
+                }],
+                "default": "/books/ideas"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(library.default_book().unwrap().name(), "Ideas");
+        round_trip(&dir, &library);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let dir = TempDir::new().unwrap();
+        let newer = format!(
+            r#"{{"version": {}, "books": [], "shelves": []}}"#,
+            LIBRARY_VERSION + 1
+        );
+
+        match load_str(&dir, &newer) {
+            Err(Error::LibraryVersion { version, .. }) => {
+                assert_eq!(version, LIBRARY_VERSION + 1)
             }
+            other => panic!("expected a version error, got {:?}", other),
         }
-        res
+        assert!(load_str(&dir, r#"{"version": "one"}"#).is_err());
     }
 }
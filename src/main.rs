@@ -1 +1,321 @@
-fn main() {}
+extern crate atty;
+#[macro_use]
+extern crate clap;
+extern crate sanctuary;
+extern crate termcolor;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use sanctuary::file_handler::FileHandler;
+use sanctuary::git::{self, GitBackend};
+use sanctuary::index::ChapterIndex;
+use sanctuary::printer::{Print, Printer};
+use sanctuary::prompt::Dialoguer;
+use sanctuary::reader::{Read, Reader};
+use sanctuary::types::{BookChoice, CliFlag, ParentChapter};
+use sanctuary::utils::exit_w_code;
+use sanctuary::{IdeaBook, Result};
+use std::env;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use termcolor::{ColorChoice, StandardStream, WriteColor};
+
+fn main() {
+    let matches = App::new("sanctuary")
+        .version(env!("CARGO_PKG_VERSION"))
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about("Write down your ideas into an mdBook")
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("config-dir")
+                .long("config-dir")
+                .value_name("DIR")
+                .takes_value(true)
+                .global(true)
+                .help("Directory to keep the config in [env: SANCTUARY_HOME]"),
+        )
+        .arg(
+            Arg::with_name("book")
+                .long("book")
+                .value_name("BOOK")
+                .takes_value(true)
+                .global(true)
+                .help("Work on this library book, by name or path, instead of the default one"),
+        )
+        .arg(
+            Arg::with_name("pick-book")
+                .long("pick-book")
+                .global(true)
+                .conflicts_with("book")
+                .help("Choose the library book to work on"),
+        )
+        .subcommand(
+            SubCommand::with_name(CliFlag::Capture.value())
+                .about("Write down a new idea (default when no command is given)")
+                .arg(
+                    Arg::with_name("parent")
+                        .long("parent")
+                        .value_name("CHAPTER")
+                        .takes_value(true)
+                        .help("Capture the idea as a sub-chapter of CHAPTER, by name or path"),
+                )
+                .arg(
+                    Arg::with_name("pick-parent")
+                        .long("pick-parent")
+                        .conflicts_with("parent")
+                        .help("Choose the chapter to capture the idea under"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(CliFlag::Add.value())
+                .about("Write down a new idea without prompts, e.g. from a script")
+                .arg(
+                    Arg::with_name("summary")
+                        .value_name("SUMMARY")
+                        .required(true)
+                        .help("Summary of the idea, used as the chapter title"),
+                )
+                .arg(
+                    Arg::with_name("body")
+                        .long("body")
+                        .value_name("TEXT")
+                        .takes_value(true)
+                        .help("Text of the chapter [default: stdin, or the editor on a terminal]"),
+                )
+                .arg(
+                    Arg::with_name("body-file")
+                        .long("body-file")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .conflicts_with("body")
+                        .help("Read the text of the chapter from PATH"),
+                )
+                .arg(
+                    Arg::with_name("parent")
+                        .long("parent")
+                        .value_name("CHAPTER")
+                        .takes_value(true)
+                        .help("Add the idea as a sub-chapter of CHAPTER, by name or path"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(CliFlag::View.value())
+                .visible_alias(CliFlag::ShortView.value())
+                .about("View one of the chapters")
+                .arg(search_body_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name(CliFlag::Open.value())
+                .about("Open an existing chapter in your editor")
+                .arg(search_body_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name(CliFlag::Build.value())
+                .visible_alias(CliFlag::BuildBook.value())
+                .about("Build the book with mdBook"),
+        )
+        .subcommand(
+            SubCommand::with_name(CliFlag::Sync.value())
+                .about("Commit pending ideas, rebase them onto the remote and push"),
+        )
+        .subcommand(
+            SubCommand::with_name(CliFlag::Library.value())
+                .about("Manage the books sanctuary knows about")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Add the mdBook at PATH, named after its title")
+                        .arg(Arg::with_name("path").value_name("PATH").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .about("Forget a book, leaving its files alone")
+                        .arg(book_arg()),
+                )
+                .subcommand(SubCommand::with_name("list").about("List the books"))
+                .subcommand(
+                    SubCommand::with_name("refresh")
+                        .about("Reread the chapters of every book and report what changed"),
+                )
+                .subcommand(
+                    SubCommand::with_name("scan")
+                        .about("Look for books under DIR and offer to add the new ones")
+                        .arg(Arg::with_name("dir").value_name("DIR").required(true))
+                        .arg(
+                            Arg::with_name("depth")
+                                .long("depth")
+                                .value_name("N")
+                                .takes_value(true)
+                                .default_value("3")
+                                .help("How many directories deep to look"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("default")
+                        .about("Work on this book unless --book says otherwise")
+                        .arg(book_arg()),
+                )
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Show a book and its chapters")
+                        .arg(book_arg()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(CliFlag::ClearRepo.value())
+                .about("Forget the configured idea repository"),
+        )
+        .subcommand(
+            SubCommand::with_name(CliFlag::ClearEditor.value())
+                .about("Forget the configured editor"),
+        )
+        .get_matches();
+
+    let mut printer = Printer {
+        writer: StandardStream::stdout(ColorChoice::Auto),
+    };
+    let fh = match FileHandler::from_env(matches.value_of("config-dir")) {
+        Ok(fh) => fh,
+        Err(e) => {
+            printer.print_error(&e.to_string());
+            exit_w_code(1);
+            return;
+        }
+    };
+
+    let stdin = io::stdin();
+    let mut idea_book = IdeaBook {
+        fh,
+        printer,
+        reader: Reader {
+            reader: stdin.lock(),
+        },
+        index: ChapterIndex::default(),
+        git: git_backend(),
+        prompter: Box::new(Dialoguer),
+        book: book_choice(&matches),
+        env: Box::new(|name| env::var(name).ok()),
+    };
+
+    if let Err(e) = dispatch(&mut idea_book, &matches) {
+        idea_book.printer.print_error(&format!("Error: {}", e));
+        exit_w_code(1);
+    }
+
+    exit_w_code(0);
+}
+
+fn dispatch<W, R>(idea_book: &mut IdeaBook<W, R>, matches: &ArgMatches) -> Result<()>
+where
+    W: Write + WriteColor,
+    R: BufRead,
+{
+    idea_book.migrate_config()?;
+
+    let (command, args) = match matches.subcommand() {
+        ("", _) => (CliFlag::Capture.value(), None),
+        (command, args) => (command, args),
+    };
+
+    if command == CliFlag::ClearRepo.value() {
+        idea_book.clear_repo()
+    } else if command == CliFlag::ClearEditor.value() {
+        idea_book.clear_editor()
+    } else if command == CliFlag::Capture.value() {
+        let parent = match args {
+            Some(args) if args.is_present("pick-parent") => ParentChapter::Pick,
+            Some(args) => match args.value_of("parent") {
+                Some(name) => ParentChapter::Named(name.to_string()),
+                None => ParentChapter::TopLevel,
+            },
+            None => ParentChapter::TopLevel,
+        };
+        idea_book.run(&parent)
+    } else if command == CliFlag::Add.value() {
+        let args = args.expect("add has a required argument");
+        let body = if let Some(body) = args.value_of("body") {
+            Some(body.to_string())
+        } else if let Some(path) = args.value_of("body-file") {
+            Some(fs::read_to_string(path)?)
+        } else if !atty::is(atty::Stream::Stdin) {
+            Some(idea_book.reader.read_all()?)
+        } else {
+            None
+        };
+        let parent = match args.value_of("parent") {
+            Some(name) => ParentChapter::Named(name.to_string()),
+            None => ParentChapter::TopLevel,
+        };
+        idea_book.add(args.value_of("summary").unwrap(), body, &parent)
+    } else if command == CliFlag::View.value() {
+        idea_book.mapthebook()?;
+        let book = idea_book.open_book()?;
+        idea_book.read_chapter(book, search_body(args))
+    } else if command == CliFlag::Open.value() {
+        idea_book.mapthebook()?;
+        idea_book.open_idea_existing(search_body(args))
+    } else if command == CliFlag::Build.value() {
+        idea_book.build_book()
+    } else if command == CliFlag::Sync.value() {
+        idea_book.sync()
+    } else if command == CliFlag::Library.value() {
+        match args.expect("library requires a subcommand").subcommand() {
+            ("add", Some(args)) => idea_book.library_add(args.value_of("path").unwrap()),
+            ("remove", Some(args)) => idea_book.library_remove(args.value_of("name").unwrap()),
+            ("list", _) => idea_book.library_list(),
+            ("refresh", _) => idea_book.library_refresh(),
+            ("scan", Some(args)) => {
+                let depth = value_t!(args, "depth", usize).unwrap_or_else(|e| e.exit());
+                idea_book.library_scan(args.value_of("dir").unwrap(), depth)
+            }
+            ("default", Some(args)) => {
+                idea_book.library_set_default(args.value_of("name").unwrap())
+            }
+            ("show", Some(args)) => idea_book.library_show(args.value_of("name").unwrap()),
+            _ => unreachable!("clap only accepts known subcommands"),
+        }
+    } else {
+        unreachable!("clap only accepts known subcommands")
+    }
+}
+
+/// Reads `--book` and `--pick-book`, which may come before or after the
+/// subcommand.
+fn book_choice(matches: &ArgMatches) -> BookChoice {
+    let args = matches.subcommand().1.unwrap_or(matches);
+    if let Some(book) = args.value_of("book").or_else(|| matches.value_of("book")) {
+        BookChoice::Named(book.to_string())
+    } else if args.is_present("pick-book") || matches.is_present("pick-book") {
+        BookChoice::Pick
+    } else {
+        BookChoice::Default
+    }
+}
+
+fn book_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("name")
+        .value_name("BOOK")
+        .required(true)
+        .help("Name or path of the book")
+}
+
+fn search_body_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("search-body")
+        .short("b")
+        .long("search-body")
+        .help("Match the search against the text of chapters too")
+}
+
+fn search_body(args: Option<&ArgMatches>) -> bool {
+    matches!(args, Some(args) if args.is_present("search-body"))
+}
+
+#[cfg(feature = "libgit2")]
+fn git_backend() -> Box<dyn GitBackend> {
+    Box::new(git::Libgit2)
+}
+
+#[cfg(not(feature = "libgit2"))]
+fn git_backend() -> Box<dyn GitBackend> {
+    Box::new(git::Shell)
+}
@@ -2,6 +2,10 @@ extern crate termcolor;
 
 use std::io;
 
+use crate::git::{SyncReport, Upstream};
+use crate::library::{Drift, Library, Member, Part, Scan};
+use crate::types::ConfigFile;
+
 use self::termcolor::{Color, ColorSpec};
 
 pub struct Printer<W> {
@@ -17,13 +21,15 @@ pub struct PrintOptions {
 pub trait Print {
     fn print(&mut self, value: &str);
     fn println(&mut self, value: &str, opts: PrintOptions);
-    fn print_input_header(&mut self, value: &str);
-    fn print_editor_selection_header(&mut self);
     fn print_fts_banner(&mut self);
     fn flush(&mut self) -> io::Result<()>;
-    fn print_chapter_selection_header(&mut self);
-    fn print_author_input_header(&mut self);
-    fn print_title_input_header(&mut self);
+    fn print_error(&mut self, value: &str);
+    fn print_migration_report(&mut self, config_path: &str, moved: &[ConfigFile]);
+    fn print_sync_report(&mut self, upstream: &Upstream, report: &SyncReport);
+    fn print_library(&mut self, library: &Library);
+    fn print_book(&mut self, book: &Member);
+    fn print_scan_report(&mut self, scan: &Scan);
+    fn print_drift_report(&mut self, drifts: &[Drift]);
 }
 
 impl<W: io::Write + termcolor::WriteColor> Print for Printer<W> {
@@ -42,51 +48,43 @@ impl<W: io::Write + termcolor::WriteColor> Print for Printer<W> {
         self.writer.reset().expect("Could not reset stdout");
     }
 
-    fn print_input_header(&mut self, value: &str) {
+    fn print_error(&mut self, value: &str) {
         let opts = PrintOptions {
-            color: Color::Green,
+            color: Color::Red,
             is_bold: true,
         };
         self.println(value, opts);
-        self.print("> ");
-    }
-
-    fn print_editor_selection_header(&mut self) {
-        let opts = PrintOptions {
-            color: Color::Green,
-            is_bold: true,
-        };
-        let text = "What editor do you want to use for writing down your ideas?";
-        self.println(text, opts);
         self.flush().unwrap();
     }
 
-    fn print_author_input_header(&mut self) {
-        let opts = PrintOptions {
-            color: Color::Green,
-            is_bold: true,
-        };
-        let text = "What's your name?";
-        self.println(text, opts);
-        self.flush().unwrap();
-    }
-    fn print_title_input_header(&mut self) {
+    fn print_migration_report(&mut self, config_path: &str, moved: &[ConfigFile]) {
         let opts = PrintOptions {
-            color: Color::Green,
-            is_bold: true,
+            color: Color::Yellow,
+            is_bold: false,
         };
-        let text = "What's the title of your book?";
-        self.println(text, opts);
+        self.println(
+            &format!("Moved legacy ~/.eureka settings into {}:", config_path),
+            opts,
+        );
+        for config in moved {
+            self.println(&format!("  - {}", config.value()), opts);
+        }
         self.flush().unwrap();
     }
 
-    fn print_chapter_selection_header(&mut self) {
+    fn print_sync_report(&mut self, upstream: &Upstream, report: &SyncReport) {
         let opts = PrintOptions {
             color: Color::Green,
-            is_bold: true,
+            is_bold: false,
         };
-        let text = "Here are your chapters, please choose which one you want to see";
-        self.println(text, opts);
+        let mut text = format!(
+            "Synced with {}/{}: pulled {}, pushed {}",
+            upstream.remote, upstream.branch, report.pulled, report.pushed
+        );
+        if report.committed {
+            text.push_str(" (committed pending changes first)");
+        }
+        self.println(&text, opts);
         self.flush().unwrap();
     }
 
@@ -129,6 +127,106 @@ impl<W: io::Write + termcolor::WriteColor> Print for Printer<W> {
         }
     }
 
+    fn print_library(&mut self, library: &Library) {
+        if library.books().is_empty() {
+            self.print("No books yet, add one with `sanctuary library add <path>`\n");
+            return;
+        }
+        let opts = PrintOptions {
+            color: Color::Green,
+            is_bold: true,
+        };
+        let default = library.default_book().map(Member::path);
+        for book in library.books() {
+            if Some(book.path()) == default {
+                self.println(&format!("{} (default)", book.name()), opts);
+            } else {
+                self.println(book.name(), opts);
+            }
+            self.print(&format!("  {}\n", book.path().display()));
+        }
+        self.flush().unwrap();
+    }
+
+    fn print_book(&mut self, book: &Member) {
+        let opts = PrintOptions {
+            color: Color::Green,
+            is_bold: true,
+        };
+        self.println(book.name(), opts);
+        self.print(&format!("{}\n", book.path().display()));
+        self.print("Chapters:\n");
+        for part in book.parts() {
+            let path = part.path().strip_prefix(book.path()).unwrap_or(part.path());
+            self.print(&format!("  {} ({})\n", part.name(), path.display()));
+        }
+        self.flush().unwrap();
+    }
+
+    fn print_scan_report(&mut self, scan: &Scan) {
+        for (path, e) in &scan.failed {
+            self.print_error(&format!("Could not load {}: {}", path.display(), e));
+        }
+        if !scan.known.is_empty() {
+            self.print(&format!(
+                "Skipping {} book(s) already in the library\n",
+                scan.known.len()
+            ));
+        }
+        if scan.new.is_empty() {
+            self.print("No new books found\n");
+        }
+        self.flush().unwrap();
+    }
+
+    fn print_drift_report(&mut self, drifts: &[Drift]) {
+        let line = |color, is_bold| PrintOptions { color, is_bold };
+        let relative = |book: &Member, part: &Part| {
+            let path = part.path().strip_prefix(book.path()).unwrap_or(part.path());
+            format!("{} ({})", part.name(), path.display())
+        };
+        for drift in drifts {
+            let book = match drift {
+                Drift::Vanished(book) => book,
+                Drift::Unreadable { book, .. } => book,
+                Drift::Chapters { book, .. } => book,
+            };
+            self.println(
+                &format!("{} {}", book.name(), book.path().display()),
+                line(Color::White, true),
+            );
+            match drift {
+                Drift::Vanished(_) => self.println(
+                    "  ! the book is gone, `sanctuary library remove` forgets it",
+                    line(Color::Yellow, false),
+                ),
+                Drift::Unreadable { error, .. } => {
+                    self.println(&format!("  ! {}", error), line(Color::Yellow, false))
+                }
+                Drift::Chapters {
+                    added,
+                    removed,
+                    missing,
+                    ..
+                } => {
+                    for part in added {
+                        let text = format!("  + {}", relative(book, part));
+                        self.println(&text, line(Color::Green, false));
+                    }
+                    for part in removed {
+                        let text = format!("  - {}", relative(book, part));
+                        self.println(&text, line(Color::Red, false));
+                    }
+                    for part in missing {
+                        let text = format!("  ! {} has no file", relative(book, part));
+                        self.println(&text, line(Color::Yellow, false));
+                    }
+                }
+            }
+        }
+        self.flush().unwrap();
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.writer.flush()
     }
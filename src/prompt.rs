@@ -0,0 +1,289 @@
+use console::{style, truncate_str, Key, Term};
+use dialoguer::{Confirm, Input, Select};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::collections::VecDeque;
+use std::io;
+
+/// How many lines of the highlighted choice's preview are shown.
+const PREVIEW_LINES: usize = 5;
+
+/// Everything sanctuary asks the user, so that interactive flows can be
+/// driven by a script as well as by a person at a terminal.
+pub trait Prompter {
+    /// Asks for a line of text, which may be empty.
+    fn input(&mut self, prompt: &str) -> io::Result<String>;
+    /// Asks for one of `items`, returning its index.
+    fn select(&mut self, prompt: &str, items: &[String], default: usize) -> io::Result<usize>;
+    /// Asks a yes/no question.
+    fn confirm(&mut self, prompt: &str, default: bool) -> io::Result<bool>;
+    /// Asks for one of `choices`, narrowed down by what the user types.
+    /// `None` means nothing was chosen.
+    fn fuzzy_select(&mut self, prompt: &str, choices: &[Choice]) -> io::Result<Option<usize>>;
+}
+
+/// Something `fuzzy_select` chooses between.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Choice {
+    /// What is listed.
+    pub label: String,
+    /// Further text the query is matched against, e.g. a path.
+    pub keywords: String,
+    /// Shown below the list while the choice is highlighted.
+    pub preview: String,
+}
+
+/// Indices of the choices matching `query`, best match first. An empty
+/// query matches everything in its original order.
+pub fn rank(choices: &[Choice], query: &str) -> Vec<usize> {
+    let matcher = SkimMatcherV2::default();
+    let mut scored: Vec<(i64, usize)> = choices
+        .iter()
+        .enumerate()
+        .filter_map(|(i, choice)| {
+            if query.is_empty() {
+                return Some((0, i));
+            }
+            let label = matcher.fuzzy_match(&choice.label, query);
+            let keywords = matcher.fuzzy_match(&choice.keywords, query);
+            label.max(keywords).map(|score| (score, i))
+        })
+        .collect();
+    scored.sort_by_key(|&(score, i)| (-score, i));
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Prompts on the terminal with dialoguer.
+pub struct Dialoguer;
+
+impl Prompter for Dialoguer {
+    fn input(&mut self, prompt: &str) -> io::Result<String> {
+        Input::<String>::new()
+            .with_prompt(prompt)
+            .allow_empty(true)
+            .interact()
+            .map(|input| input.trim().to_string())
+    }
+
+    fn select(&mut self, prompt: &str, items: &[String], default: usize) -> io::Result<usize> {
+        Select::new()
+            .with_prompt(prompt)
+            .items(items)
+            .default(default)
+            .paged(true)
+            .interact()
+    }
+
+    fn confirm(&mut self, prompt: &str, default: bool) -> io::Result<bool> {
+        Confirm::new()
+            .with_prompt(prompt)
+            .default(default)
+            .interact()
+    }
+
+    fn fuzzy_select(&mut self, prompt: &str, choices: &[Choice]) -> io::Result<Option<usize>> {
+        let term = Term::stderr();
+        term.hide_cursor()?;
+        let picked = pick(&term, prompt, choices);
+        term.show_cursor()?;
+        picked
+    }
+}
+
+/// Filters `choices` live as the user types, until one is picked with enter
+/// or the search is left with escape.
+fn pick(term: &Term, prompt: &str, choices: &[Choice]) -> io::Result<Option<usize>> {
+    let (rows, columns) = term.size();
+    let width = usize::from(columns).saturating_sub(1);
+    let height = usize::from(rows)
+        .saturating_sub(PREVIEW_LINES + 3)
+        .clamp(1, 10);
+    let mut query = String::new();
+    let mut selected = 0;
+    let mut drawn = 0;
+
+    loop {
+        let matches = rank(choices, &query);
+        selected = selected.min(matches.len().saturating_sub(1));
+
+        let mut lines = vec![format!("{} {}", style(prompt).bold(), query)];
+        let top = (selected + 1).saturating_sub(height);
+        for (row, &i) in matches.iter().enumerate().skip(top).take(height) {
+            let label = &choices[i].label;
+            if row == selected {
+                lines.push(format!("{} {}", style(">").cyan(), style(label).cyan()));
+            } else {
+                lines.push(format!("  {}", label));
+            }
+        }
+        match matches.get(selected) {
+            Some(&i) => {
+                lines.push(String::new());
+                let preview = choices[i].preview.lines().take(PREVIEW_LINES);
+                lines.extend(preview.map(|line| style(line).dim().to_string()));
+            }
+            None => lines.push(style("  nothing matches").dim().to_string()),
+        }
+
+        term.clear_last_lines(drawn)?;
+        for line in &lines {
+            term.write_line(&truncate_str(line, width, "…"))?;
+        }
+        drawn = lines.len();
+
+        match term.read_key()? {
+            Key::Enter if !matches.is_empty() => {
+                term.clear_last_lines(drawn)?;
+                return Ok(Some(matches[selected]));
+            }
+            Key::Escape => {
+                term.clear_last_lines(drawn)?;
+                return Ok(None);
+            }
+            Key::ArrowUp => selected = selected.saturating_sub(1),
+            Key::ArrowDown => selected += 1,
+            Key::Backspace => {
+                query.pop();
+                selected = 0;
+            }
+            Key::Char(c) if !c.is_control() => {
+                query.push(c);
+                selected = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Answers prompts from a list of canned replies, as if typed in order.
+///
+/// Inputs get the reply as is. Confirmations accept `y`, `yes`, `n`, `no`,
+/// or an empty reply for the default. Selections pick the first item
+/// containing the reply, ignoring case, and fuzzy selections treat an empty
+/// reply as cancelling and otherwise pick the best match.
+#[derive(Debug, Default, Clone)]
+pub struct Scripted {
+    replies: VecDeque<String>,
+}
+
+impl Scripted {
+    pub fn new(replies: &[&str]) -> Self {
+        Scripted {
+            replies: replies.iter().map(|reply| reply.to_string()).collect(),
+        }
+    }
+
+    fn next(&mut self, prompt: &str) -> io::Result<String> {
+        self.replies.pop_front().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("no scripted reply left for \"{}\"", prompt),
+            )
+        })
+    }
+}
+
+impl Prompter for Scripted {
+    fn input(&mut self, prompt: &str) -> io::Result<String> {
+        self.next(prompt)
+    }
+
+    fn select(&mut self, prompt: &str, items: &[String], _default: usize) -> io::Result<usize> {
+        let reply = self.next(prompt)?;
+        filter(items, &reply).first().copied().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("\"{}\" is not one of the choices for \"{}\"", reply, prompt),
+            )
+        })
+    }
+
+    fn confirm(&mut self, prompt: &str, default: bool) -> io::Result<bool> {
+        let reply = self.next(prompt)?;
+        match reply.to_lowercase().as_str() {
+            "" => Ok(default),
+            "y" | "yes" => Ok(true),
+            "n" | "no" => Ok(false),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("\"{}\" does not answer \"{}\"", reply, prompt),
+            )),
+        }
+    }
+
+    fn fuzzy_select(&mut self, prompt: &str, choices: &[Choice]) -> io::Result<Option<usize>> {
+        let reply = self.next(prompt)?;
+        if reply.is_empty() {
+            return Ok(None);
+        }
+        Ok(rank(choices, &reply).first().copied())
+    }
+}
+
+/// Indices of the items containing `query`, ignoring case.
+fn filter(items: &[String], query: &str) -> Vec<usize> {
+    let query = query.to_lowercase();
+    items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| item.to_lowercase().contains(&query))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripted_replies_answer_prompts_in_order() {
+        let items = vec!["1. Bread".to_string(), "  1.1. Rye bread".to_string()];
+        let choices: Vec<Choice> = items
+            .iter()
+            .map(|label| Choice {
+                label: label.clone(),
+                ..Choice::default()
+            })
+            .collect();
+        let mut prompter = Scripted::new(&["Ada", "rye", "", "n", "rye", ""]);
+
+        assert_eq!(prompter.input("Name").unwrap(), "Ada");
+        assert_eq!(prompter.select("Chapter", &items, 0).unwrap(), 1);
+        assert!(prompter.confirm("Create?", true).unwrap());
+        assert!(!prompter.confirm("Create?", true).unwrap());
+        assert_eq!(prompter.fuzzy_select("Chapter", &choices).unwrap(), Some(1));
+        assert_eq!(prompter.fuzzy_select("Chapter", &choices).unwrap(), None);
+        assert_eq!(
+            prompter.input("Name").unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn ranking_matches_labels_and_keywords() {
+        let choices = vec![
+            Choice {
+                label: "1. Bread".to_string(),
+                keywords: "bread.md".to_string(),
+                preview: String::new(),
+            },
+            Choice {
+                label: "2. Travel".to_string(),
+                keywords: "travel.md Pack light, bring bread".to_string(),
+                preview: String::new(),
+            },
+            Choice {
+                label: "3. Sourdough".to_string(),
+                keywords: "bread/sourdough.md".to_string(),
+                preview: String::new(),
+            },
+        ];
+
+        assert_eq!(rank(&choices, ""), vec![0, 1, 2]);
+        assert_eq!(rank(&choices, "trvl"), vec![1]);
+        assert_eq!(rank(&choices, "3."), vec![2]);
+        assert_eq!(rank(&choices, "light"), vec![1]);
+        assert_eq!(rank(&choices, "bread").len(), 3);
+        assert!(rank(&choices, "zebra").is_empty());
+    }
+}
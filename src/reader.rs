@@ -6,6 +6,8 @@ pub struct Reader<R> {
 
 pub trait Read {
     fn read(&mut self) -> String;
+    /// Reads everything that is left, e.g. a chapter piped in on stdin.
+    fn read_all(&mut self) -> io::Result<String>;
 }
 
 impl<R: io::BufRead> Read for Reader<R> {
@@ -14,4 +16,10 @@ impl<R: io::BufRead> Read for Reader<R> {
         self.reader.read_line(&mut input).unwrap();
         input.trim().to_string()
     }
+
+    fn read_all(&mut self) -> io::Result<String> {
+        let mut input = String::new();
+        self.reader.read_to_string(&mut input)?;
+        Ok(input)
+    }
 }
@@ -0,0 +1,512 @@
+//! Renders chapter markdown for the terminal, so chapters can be viewed
+//! without any external tools.
+
+use console::{measure_text_width, Key, Term};
+use pulldown_cmark::{Alignment, Event, LinkType, Options, Parser, Tag};
+use std::io;
+use termcolor::{Buffer, Color, ColorSpec, NoColor, WriteColor};
+
+/// Renders `markdown` to stdout, paging through it when stdout is a terminal.
+pub fn show(markdown: &str) -> io::Result<()> {
+    let term = Term::stdout();
+    if !term.is_term() {
+        return render(markdown, &mut NoColor::new(io::stdout()));
+    }
+
+    let mut buffer = Buffer::ansi();
+    render(markdown, &mut buffer)?;
+    page(&String::from_utf8_lossy(buffer.as_slice()), &term)
+}
+
+/// Writes `markdown` to `out` with headings, emphasis, lists, code, quotes,
+/// links and tables styled through `termcolor`.
+pub fn render<W: WriteColor>(markdown: &str, out: &mut W) -> io::Result<()> {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS;
+    let mut renderer = Renderer::new(out);
+    for event in Parser::new_ext(markdown, options) {
+        renderer.event(event)?;
+    }
+    renderer.finish()
+}
+
+/// Shows `text` a screen at a time: space for the next page, enter for the
+/// next line, q to stop.
+fn page(text: &str, term: &Term) -> io::Result<()> {
+    let lines: Vec<&str> = text.lines().collect();
+    let height = term.size().0 as usize;
+    let screen = height.saturating_sub(1).max(1);
+    if lines.len() <= height {
+        return term.write_str(text);
+    }
+
+    let mut shown = 0;
+    let mut step = screen;
+    while shown < lines.len() {
+        let end = (shown + step).min(lines.len());
+        for line in &lines[shown..end] {
+            term.write_line(line)?;
+        }
+        shown = end;
+        if shown == lines.len() {
+            break;
+        }
+
+        term.write_str(
+            &console::style("-- more -- (space, enter or q)")
+                .dim()
+                .to_string(),
+        )?;
+        let key = term.read_key()?;
+        term.clear_line()?;
+        step = match key {
+            Key::Char('q') | Key::Escape => break,
+            Key::Enter | Key::ArrowDown | Key::Char('j') => 1,
+            _ => screen,
+        };
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Table {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<String>>,
+    /// Rows belonging to the header, always the first ones.
+    head: usize,
+}
+
+struct Renderer<'w, W> {
+    out: &'w mut W,
+    /// Styles of the enclosing inline elements, innermost last.
+    styles: Vec<ColorSpec>,
+    /// Written at the start of each line: quote bars and list indentation.
+    prefixes: Vec<(String, ColorSpec)>,
+    /// Next number of each enclosing list, `None` for bulleted lists.
+    lists: Vec<Option<u64>>,
+    /// Destination of the link being rendered.
+    links: Vec<String>,
+    table: Option<Table>,
+    at_line_start: bool,
+    /// Whether the next block should be separated by a blank line.
+    separate: bool,
+}
+
+impl<'w, W: WriteColor> Renderer<'w, W> {
+    fn new(out: &'w mut W) -> Self {
+        Renderer {
+            out,
+            styles: Vec::new(),
+            prefixes: Vec::new(),
+            lists: Vec::new(),
+            links: Vec::new(),
+            table: None,
+            at_line_start: true,
+            separate: false,
+        }
+    }
+
+    fn event(&mut self, event: Event) -> io::Result<()> {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.text(&text),
+            Event::Code(code) => {
+                let mut spec = self.style();
+                spec.set_fg(Some(Color::Yellow));
+                self.write_styled(&code, &spec)
+            }
+            Event::Html(html) => self.text(&html),
+            Event::FootnoteReference(label) => self.text(&format!("[^{}]", label)),
+            Event::SoftBreak => self.text(" "),
+            Event::HardBreak => self.newline(),
+            Event::Rule => {
+                self.block()?;
+                let mut spec = ColorSpec::new();
+                spec.set_fg(Some(Color::Black)).set_intense(true);
+                self.write_styled(&"─".repeat(40), &spec)?;
+                self.end_block()
+            }
+            Event::TaskListMarker(done) => self.text(if done { "[x] " } else { "[ ] " }),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) -> io::Result<()> {
+        match tag {
+            Tag::Paragraph => self.block(),
+            Tag::Heading(level) => {
+                self.block()?;
+                let mut spec = ColorSpec::new();
+                spec.set_bold(true).set_fg(Some(match level {
+                    1 => Color::Magenta,
+                    2 => Color::Cyan,
+                    _ => Color::Blue,
+                }));
+                if level == 1 {
+                    spec.set_underline(true);
+                }
+                self.styles.push(spec);
+                self.text(&format!("{} ", "#".repeat(level as usize)))
+            }
+            Tag::BlockQuote => {
+                self.block()?;
+                let mut spec = ColorSpec::new();
+                spec.set_fg(Some(Color::Green));
+                self.prefixes.push(("│ ".to_string(), spec));
+                let mut spec = self.style();
+                spec.set_italic(true);
+                self.styles.push(spec);
+                Ok(())
+            }
+            Tag::CodeBlock(_) => {
+                self.block()?;
+                self.prefixes.push(("    ".to_string(), ColorSpec::new()));
+                let mut spec = ColorSpec::new();
+                spec.set_fg(Some(Color::Green));
+                self.styles.push(spec);
+                Ok(())
+            }
+            Tag::List(first) => {
+                if self.lists.is_empty() {
+                    self.block()?;
+                } else {
+                    self.end_line()?;
+                }
+                self.lists.push(first);
+                Ok(())
+            }
+            Tag::Item => {
+                self.end_line()?;
+                self.separate = false;
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                let mut spec = ColorSpec::new();
+                spec.set_bold(true);
+                self.write_styled(&marker, &spec)?;
+                let indent = " ".repeat(measure_text_width(&marker));
+                self.prefixes.push((indent, ColorSpec::new()));
+                Ok(())
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.block()?;
+                self.text(&format!("[^{}]: ", label))
+            }
+            Tag::Table(alignments) => {
+                self.block()?;
+                self.table = Some(Table {
+                    alignments,
+                    ..Table::default()
+                });
+                Ok(())
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(ref mut table) = self.table {
+                    table.rows.push(Vec::new());
+                }
+                Ok(())
+            }
+            Tag::TableCell => {
+                if let Some(row) = self.table.as_mut().and_then(|table| table.rows.last_mut()) {
+                    row.push(String::new());
+                }
+                Ok(())
+            }
+            Tag::Emphasis => {
+                let mut spec = self.style();
+                spec.set_italic(true);
+                self.styles.push(spec);
+                Ok(())
+            }
+            Tag::Strong => {
+                let mut spec = self.style();
+                spec.set_bold(true);
+                self.styles.push(spec);
+                Ok(())
+            }
+            Tag::Strikethrough => Ok(()),
+            Tag::Link(link_type, url, _) => {
+                let mut spec = self.style();
+                spec.set_underline(true).set_fg(Some(Color::Blue));
+                self.styles.push(spec);
+                // Autolinks already show their destination
+                let url = match link_type {
+                    LinkType::Autolink | LinkType::Email => String::new(),
+                    _ => url.to_string(),
+                };
+                self.links.push(url);
+                Ok(())
+            }
+            Tag::Image(_, url, _) => {
+                self.links.push(url.to_string());
+                self.text("[image: ")
+            }
+        }
+    }
+
+    fn end(&mut self, tag: Tag) -> io::Result<()> {
+        match tag {
+            Tag::Paragraph | Tag::FootnoteDefinition(_) => self.end_block(),
+            Tag::Heading(_) => {
+                self.styles.pop();
+                self.end_block()
+            }
+            Tag::BlockQuote | Tag::CodeBlock(_) => {
+                self.styles.pop();
+                self.end_line()?;
+                self.prefixes.pop();
+                self.separate = true;
+                Ok(())
+            }
+            Tag::List(_) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.separate = true;
+                }
+                Ok(())
+            }
+            Tag::Item => {
+                self.end_line()?;
+                self.prefixes.pop();
+                Ok(())
+            }
+            Tag::Table(_) => {
+                if let Some(table) = self.table.take() {
+                    self.write_table(&table)?;
+                }
+                self.separate = true;
+                Ok(())
+            }
+            Tag::TableHead => {
+                if let Some(ref mut table) = self.table {
+                    table.head = table.rows.len();
+                }
+                Ok(())
+            }
+            Tag::TableRow | Tag::TableCell | Tag::Strikethrough => Ok(()),
+            Tag::Emphasis | Tag::Strong => {
+                self.styles.pop();
+                Ok(())
+            }
+            Tag::Link(..) => {
+                self.styles.pop();
+                match self.links.pop() {
+                    Some(ref url) if !url.is_empty() => self.text(&format!(" ({})", url)),
+                    _ => Ok(()),
+                }
+            }
+            Tag::Image(..) => {
+                let url = self.links.pop().unwrap_or_default();
+                self.text(&format!("] ({})", url))
+            }
+        }
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.end_line()?;
+        self.out.reset()
+    }
+
+    fn style(&self) -> ColorSpec {
+        self.styles.last().cloned().unwrap_or_default()
+    }
+
+    fn text(&mut self, text: &str) -> io::Result<()> {
+        if let Some(cell) = self
+            .table
+            .as_mut()
+            .and_then(|table| table.rows.last_mut())
+            .and_then(|row| row.last_mut())
+        {
+            cell.push_str(text);
+            return Ok(());
+        }
+        let spec = self.style();
+        self.write_styled(text, &spec)
+    }
+
+    /// Writes `text`, starting every new line with the current prefixes.
+    fn write_styled(&mut self, text: &str, spec: &ColorSpec) -> io::Result<()> {
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.newline()?;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            if self.at_line_start {
+                self.write_prefixes()?;
+            }
+            self.out.set_color(spec)?;
+            write!(self.out, "{}", line)?;
+            self.out.reset()?;
+        }
+        Ok(())
+    }
+
+    fn write_prefixes(&mut self) -> io::Result<()> {
+        for (prefix, spec) in &self.prefixes {
+            self.out.set_color(spec)?;
+            write!(self.out, "{}", prefix)?;
+        }
+        self.out.reset()?;
+        self.at_line_start = false;
+        Ok(())
+    }
+
+    fn newline(&mut self) -> io::Result<()> {
+        if self.at_line_start {
+            // Keep quote bars going through blank lines
+            self.write_prefixes()?;
+        }
+        writeln!(self.out)?;
+        self.at_line_start = true;
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> io::Result<()> {
+        if !self.at_line_start {
+            self.newline()?;
+        }
+        Ok(())
+    }
+
+    /// Starts a block, leaving a blank line after the previous one.
+    fn block(&mut self) -> io::Result<()> {
+        self.end_line()?;
+        if self.separate {
+            self.newline()?;
+            self.separate = false;
+        }
+        Ok(())
+    }
+
+    fn end_block(&mut self) -> io::Result<()> {
+        self.end_line()?;
+        self.separate = true;
+        Ok(())
+    }
+
+    fn write_table(&mut self, table: &Table) -> io::Result<()> {
+        let columns = table.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                table
+                    .rows
+                    .iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| measure_text_width(cell))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let mut border = ColorSpec::new();
+        border.set_fg(Some(Color::Black)).set_intense(true);
+        let mut header = ColorSpec::new();
+        header.set_bold(true);
+        let plain = ColorSpec::new();
+
+        for (index, row) in table.rows.iter().enumerate() {
+            for (column, width) in widths.iter().enumerate() {
+                if column > 0 {
+                    self.write_styled(" │ ", &border)?;
+                }
+                let cell = row.get(column).map(String::as_str).unwrap_or("");
+                let alignment = table
+                    .alignments
+                    .get(column)
+                    .cloned()
+                    .unwrap_or(Alignment::None);
+                let cell = pad(cell, *width, alignment);
+                let spec = if index < table.head { &header } else { &plain };
+                self.write_styled(&cell, spec)?;
+            }
+            self.newline()?;
+
+            if index + 1 == table.head {
+                let rule: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+                self.write_styled(&rule.join("─┼─"), &border)?;
+                self.newline()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn pad(text: &str, width: usize, alignment: Alignment) -> String {
+    let space = width.saturating_sub(measure_text_width(text));
+    let (left, right) = match alignment {
+        Alignment::Right => (space, 0),
+        Alignment::Center => (space / 2, space - space / 2),
+        Alignment::Left | Alignment::None => (0, space),
+    };
+    format!("{}{}{}", " ".repeat(left), text, " ".repeat(right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(markdown: &str) -> String {
+        let mut out = NoColor::new(Vec::new());
+        render(markdown, &mut out).unwrap();
+        String::from_utf8(out.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn renders_block_structure_as_plain_text() {
+        let markdown = "# Idea
+
+Some *emphasis* and a [link](https://example.com).
+
+- one
+- two
+    1. nested
+
+> quoted
+> text
+
+```
+let x = 1;
+```
+
+| Name | Count |
+|------|------:|
+| a    | 10    |
+| bb   | 2     |
+";
+        assert_eq!(
+            plain(markdown),
+            "# Idea
+
+Some emphasis and a link (https://example.com).
+
+• one
+• two
+  1. nested
+
+│ quoted text
+
+    let x = 1;
+
+Name │ Count
+─────┼──────
+a    │    10
+bb   │     2
+"
+        );
+    }
+
+    #[test]
+    fn styles_inline_elements() {
+        let mut out = Buffer::ansi();
+        render("**bold** `code`", &mut out).unwrap();
+        let rendered = String::from_utf8(out.into_inner()).unwrap();
+        assert!(rendered.contains("\u{1b}[1m"));
+        assert!(rendered.contains("\u{1b}[33m"));
+    }
+}
@@ -0,0 +1,573 @@
+//! Structured editing of a book's `SUMMARY.md`. The file is parsed with
+//! mdBook's own parser, changed as a tree and written back out, so prefix and
+//! suffix chapters, separators and part titles survive every edit.
+
+use crate::error::{Error, Result};
+use crate::file_handler::write_atomic;
+use mdbook::book::{parse_summary, SummaryItem};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// A line of the summary.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    Chapter(ChapterLink),
+    /// A `---` rule.
+    Separator,
+    /// A heading that starts a new part of the numbered chapters.
+    PartTitle(String),
+}
+
+/// A chapter entry and the sub-chapters nested under it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChapterLink {
+    pub name: String,
+    /// Path of the chapter file, relative to the source directory.
+    pub location: PathBuf,
+    pub nested: Vec<Entry>,
+}
+
+impl ChapterLink {
+    pub fn new<S: Into<String>, P: Into<PathBuf>>(name: S, location: P) -> Self {
+        ChapterLink {
+            name: name.into(),
+            location: location.into(),
+            nested: Vec::new(),
+        }
+    }
+}
+
+/// Where `insert` and `move_chapter` put a chapter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Placement {
+    /// After the last numbered entry.
+    End,
+    /// At this index among the top level numbered entries.
+    At(usize),
+    /// As the last sub-chapter of the chapter at this location.
+    Under(PathBuf),
+    /// Right before the chapter at this location, as its sibling.
+    Before(PathBuf),
+    /// Right after the chapter at this location, as its sibling.
+    After(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Prefix,
+    Numbered,
+    Suffix,
+}
+
+/// An editable `SUMMARY.md`. Chapters are addressed by their location, which
+/// mdBook requires to be unique within a book.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryEditor {
+    path: PathBuf,
+    pub title: Option<String>,
+    /// Unnumbered chapters and separators before the numbered chapters.
+    pub prefix: Vec<Entry>,
+    pub numbered: Vec<Entry>,
+    /// Unnumbered chapters and separators after the numbered chapters.
+    pub suffix: Vec<Entry>,
+}
+
+impl SummaryEditor {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| Error::Summary {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+        SummaryEditor::parse(path, &text)
+    }
+
+    /// Parses `text` as the contents of the summary at `path`.
+    pub fn parse(path: &Path, text: &str) -> Result<Self> {
+        let summary = parse_summary(text).map_err(|e| Error::Summary {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+        let scan = scan(text);
+
+        let mut numbered = entries(summary.numbered_chapters);
+        // Insert from the back so the recorded indices stay valid
+        for (index, part_title) in scan.part_titles.into_iter().rev() {
+            numbered.insert(index.min(numbered.len()), Entry::PartTitle(part_title));
+        }
+
+        Ok(SummaryEditor {
+            path: path.to_path_buf(),
+            // The raw heading keeps any styling the parser strips
+            title: summary.title.and(scan.title),
+            prefix: entries(summary.prefix_chapters),
+            numbered,
+            suffix: entries(summary.suffix_chapters),
+        })
+    }
+
+    /// Writes the summary back to the file it was loaded from.
+    pub fn save(&self) -> Result<()> {
+        write_atomic(&self.path.to_string_lossy(), &self.to_string())
+            .map_err(|e| self.error(e.to_string()))
+    }
+
+    pub fn chapter(&self, location: &Path) -> Option<&ChapterLink> {
+        let (section, indices) = self.locate(location).ok()?;
+        let mut entries = self.section(section);
+        let mut found = None;
+        for &index in &indices {
+            if let Entry::Chapter(chapter) = &entries[index] {
+                entries = &chapter.nested;
+                found = Some(chapter);
+            }
+        }
+        found
+    }
+
+    pub fn insert(&mut self, chapter: ChapterLink, at: Placement) -> Result<()> {
+        if self.locate(&chapter.location).is_ok() {
+            return Err(self.error(format!(
+                "{} is already in the summary",
+                chapter.location.display()
+            )));
+        }
+
+        let (section, indices, offset) = match at {
+            Placement::End => {
+                self.numbered.push(Entry::Chapter(chapter));
+                return Ok(());
+            }
+            Placement::At(index) => {
+                if index > self.numbered.len() {
+                    return Err(self.error(format!(
+                        "cannot insert at {}, there are only {} entries",
+                        index,
+                        self.numbered.len()
+                    )));
+                }
+                self.numbered.insert(index, Entry::Chapter(chapter));
+                return Ok(());
+            }
+            Placement::Under(parent) => {
+                let (section, indices) = self.locate(&parent)?;
+                if section != Section::Numbered {
+                    return Err(self.error(format!(
+                        "{} is not a numbered chapter and cannot have sub-chapters",
+                        parent.display()
+                    )));
+                }
+                let (last, siblings) = self.siblings_mut(section, &indices);
+                if let Entry::Chapter(parent) = &mut siblings[last] {
+                    parent.nested.push(Entry::Chapter(chapter));
+                }
+                return Ok(());
+            }
+            Placement::Before(sibling) => {
+                let (section, indices) = self.locate(&sibling)?;
+                (section, indices, 0)
+            }
+            Placement::After(sibling) => {
+                let (section, indices) = self.locate(&sibling)?;
+                (section, indices, 1)
+            }
+        };
+
+        if section != Section::Numbered && !chapter.nested.is_empty() {
+            return Err(self.error(format!(
+                "{} has sub-chapters and cannot become a prefix or suffix chapter",
+                chapter.location.display()
+            )));
+        }
+        let (last, siblings) = self.siblings_mut(section, &indices);
+        siblings.insert(last + offset, Entry::Chapter(chapter));
+        Ok(())
+    }
+
+    /// Takes the chapter at `location`, with its sub-chapters, out of the summary.
+    pub fn remove(&mut self, location: &Path) -> Result<ChapterLink> {
+        let (section, indices) = self.locate(location)?;
+        let (last, siblings) = self.siblings_mut(section, &indices);
+        match siblings.remove(last) {
+            Entry::Chapter(chapter) => Ok(chapter),
+            _ => unreachable!("locate only finds chapters"),
+        }
+    }
+
+    /// Moves the chapter at `location`, with its sub-chapters, to `to`. The
+    /// summary is left unchanged if the chapter cannot be placed there.
+    pub fn move_chapter(&mut self, location: &Path, to: Placement) -> Result<()> {
+        let before = self.clone();
+        let chapter = self.remove(location)?;
+        if let Err(e) = self.insert(chapter, to) {
+            *self = before;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub fn rename(&mut self, location: &Path, name: &str) -> Result<()> {
+        let (section, indices) = self.locate(location)?;
+        let (last, siblings) = self.siblings_mut(section, &indices);
+        if let Entry::Chapter(chapter) = &mut siblings[last] {
+            chapter.name = name.to_string();
+        }
+        Ok(())
+    }
+
+    fn section(&self, section: Section) -> &Vec<Entry> {
+        match section {
+            Section::Prefix => &self.prefix,
+            Section::Numbered => &self.numbered,
+            Section::Suffix => &self.suffix,
+        }
+    }
+
+    /// Finds the chapter at `location` as the indices leading to it.
+    fn locate(&self, location: &Path) -> Result<(Section, Vec<usize>)> {
+        let wanted = normalize(location);
+        for &section in &[Section::Prefix, Section::Numbered, Section::Suffix] {
+            if let Some(indices) = find(self.section(section), &wanted) {
+                return Ok((section, indices));
+            }
+        }
+        Err(Error::ChapterNotFound(location.display().to_string()))
+    }
+
+    /// The list holding the entry at `indices`, and its index in that list.
+    fn siblings_mut(&mut self, section: Section, indices: &[usize]) -> (usize, &mut Vec<Entry>) {
+        let (last, parents) = indices
+            .split_last()
+            .expect("locate never returns no indices");
+        let mut entries = match section {
+            Section::Prefix => &mut self.prefix,
+            Section::Numbered => &mut self.numbered,
+            Section::Suffix => &mut self.suffix,
+        };
+        for &index in parents {
+            entries = match &mut entries[index] {
+                Entry::Chapter(chapter) => &mut chapter.nested,
+                _ => unreachable!("locate only descends into chapters"),
+            };
+        }
+        (*last, entries)
+    }
+
+    fn error(&self, reason: String) -> Error {
+        Error::Summary {
+            path: self.path.clone(),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for SummaryEditor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut blocks = Vec::new();
+        if let Some(ref title) = self.title {
+            blocks.push(format!("# {}", title));
+        }
+        blocks.extend(self.prefix.iter().map(affix_block));
+
+        // Consecutive chapters form one list, anything else breaks it up
+        let mut list = String::new();
+        for entry in &self.numbered {
+            match entry {
+                Entry::Chapter(chapter) => write_list_item(&mut list, chapter, 0),
+                other => {
+                    if !list.is_empty() {
+                        blocks.push(list.trim_end().to_string());
+                        list.clear();
+                    }
+                    blocks.push(affix_block(other));
+                }
+            }
+        }
+        if !list.is_empty() {
+            blocks.push(list.trim_end().to_string());
+        }
+
+        blocks.extend(self.suffix.iter().map(affix_block));
+        writeln!(f, "{}", blocks.join("\n\n"))
+    }
+}
+
+fn affix_block(entry: &Entry) -> String {
+    match entry {
+        Entry::Chapter(chapter) => link(chapter),
+        Entry::Separator => "---".to_string(),
+        Entry::PartTitle(title) => format!("# {}", title),
+    }
+}
+
+fn write_list_item(out: &mut String, chapter: &ChapterLink, depth: usize) {
+    out.push_str(&"    ".repeat(depth));
+    out.push_str("- ");
+    out.push_str(&link(chapter));
+    out.push('\n');
+    for entry in &chapter.nested {
+        if let Entry::Chapter(nested) = entry {
+            write_list_item(out, nested, depth + 1);
+        }
+    }
+}
+
+fn link(chapter: &ChapterLink) -> String {
+    let mut name = String::new();
+    for c in chapter.name.chars() {
+        // mdBook keeps only the text of a link, so keep markup characters literal
+        if "\\[]*_`".contains(c) {
+            name.push('\\');
+        }
+        name.push(c);
+    }
+    let location = chapter.location.to_string_lossy().replace('\\', "/");
+    if location.contains(char::is_whitespace) || location.contains(&['(', ')'][..]) {
+        format!("[{}](<{}>)", name, location)
+    } else {
+        format!("[{}]({})", name, location)
+    }
+}
+
+fn entries(items: Vec<SummaryItem>) -> Vec<Entry> {
+    items
+        .into_iter()
+        .map(|item| match item {
+            SummaryItem::Link(link) => Entry::Chapter(ChapterLink {
+                name: link.name,
+                location: link.location,
+                nested: entries(link.nested_items),
+            }),
+            SummaryItem::Separator => Entry::Separator,
+        })
+        .collect()
+}
+
+fn find(entries: &[Entry], wanted: &Path) -> Option<Vec<usize>> {
+    for (index, entry) in entries.iter().enumerate() {
+        if let Entry::Chapter(chapter) = entry {
+            if normalize(&chapter.location) == wanted {
+                return Some(vec![index]);
+            }
+            if let Some(mut indices) = find(&chapter.nested, wanted) {
+                indices.insert(0, index);
+                return Some(indices);
+            }
+        }
+    }
+    None
+}
+
+/// Drops `./` components so `./idea.md` and `idea.md` compare equal.
+fn normalize(location: &Path) -> PathBuf {
+    location
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+/// What mdBook 0.3's parser does not keep: the raw title and the part titles,
+/// with the index of the numbered entry each one comes before.
+#[derive(Debug, Default)]
+struct Scan {
+    title: Option<String>,
+    part_titles: Vec<(usize, String)>,
+}
+
+/// Walks the lines of the summary the way mdBook splits it into sections.
+fn scan(text: &str) -> Scan {
+    #[derive(PartialEq)]
+    enum State {
+        Start,
+        Prefix,
+        Numbered,
+        Suffix,
+    }
+
+    let mut result = Scan::default();
+    let mut state = State::Start;
+    // Top level numbered entries seen so far, separators included
+    let mut index = 0;
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let indented = line.starts_with(char::is_whitespace);
+        let trimmed = line.trim();
+        let heading = heading(trimmed);
+        let rule = is_rule(trimmed);
+        let list_item = !rule && is_list_item(trimmed);
+
+        if state == State::Start {
+            state = State::Prefix;
+            if let Some(title) = heading {
+                result.title = Some(title);
+                continue;
+            }
+        }
+
+        match state {
+            State::Prefix => {
+                if list_item {
+                    state = State::Numbered;
+                    index = 1;
+                } else if let Some(title) = heading {
+                    result.part_titles.push((0, title));
+                }
+            }
+            State::Numbered if indented => {}
+            State::Numbered => {
+                if list_item || rule {
+                    index += 1;
+                } else if let Some(title) = heading {
+                    result.part_titles.push((index, title));
+                } else {
+                    state = State::Suffix;
+                }
+            }
+            State::Start | State::Suffix => {}
+        }
+    }
+    result
+}
+
+fn heading(line: &str) -> Option<String> {
+    let text = line.trim_start_matches('#');
+    if text.len() == line.len() || !(text.is_empty() || text.starts_with(' ')) {
+        return None;
+    }
+    Some(text.trim().trim_end_matches('#').trim_end().to_string())
+}
+
+fn is_rule(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ["-", "*", "_"]
+            .iter()
+            .any(|mark| marks.chars().all(|c| c.to_string() == *mark))
+}
+
+fn is_list_item(line: &str) -> bool {
+    ["- ", "* ", "+ "]
+        .iter()
+        .any(|marker| line.starts_with(marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUMMARY: &str = "# My *Ideas*
+
+[Preface](./preface.md)
+
+---
+
+- [Cooking](./cooking.md)
+    - [Bread](./cooking/bread.md)
+- [Travel](./travel.md)
+
+# Someday
+
+- [Boats](./boats.md)
+
+---
+
+- [Misc](./misc.md)
+
+[Afterword](./afterword.md)
+";
+
+    fn editor() -> SummaryEditor {
+        SummaryEditor::parse(Path::new("SUMMARY.md"), SUMMARY).unwrap()
+    }
+
+    #[test]
+    fn unchanged_summaries_round_trip() {
+        let summary = editor();
+        assert_eq!(summary.title.as_deref(), Some("My *Ideas*"));
+        assert_eq!(summary.numbered[2], Entry::PartTitle("Someday".to_string()));
+        assert_eq!(summary.to_string(), SUMMARY);
+        assert_eq!(
+            SummaryEditor::parse(Path::new("SUMMARY.md"), &summary.to_string()).unwrap(),
+            summary
+        );
+    }
+
+    #[test]
+    fn edits_keep_the_rest_of_the_summary() {
+        let mut summary = editor();
+        summary
+            .insert(
+                ChapterLink::new("Pasta [fresh]", "cooking/pasta.md"),
+                Placement::Under(PathBuf::from("cooking.md")),
+            )
+            .unwrap();
+        summary
+            .insert(
+                ChapterLink::new("Hiking", "hiking.md"),
+                Placement::Before(PathBuf::from("./travel.md")),
+            )
+            .unwrap();
+        summary.rename(Path::new("boats.md"), "Sailing").unwrap();
+        summary
+            .move_chapter(Path::new("./misc.md"), Placement::At(0))
+            .unwrap();
+        let removed = summary.remove(Path::new("./cooking/bread.md")).unwrap();
+        assert_eq!(removed.name, "Bread");
+
+        assert_eq!(
+            summary.to_string(),
+            "# My *Ideas*
+
+[Preface](./preface.md)
+
+---
+
+- [Misc](./misc.md)
+- [Cooking](./cooking.md)
+    - [Pasta \\[fresh\\]](cooking/pasta.md)
+- [Hiking](hiking.md)
+- [Travel](./travel.md)
+
+# Someday
+
+- [Sailing](./boats.md)
+
+---
+
+[Afterword](./afterword.md)
+"
+        );
+        let reparsed = SummaryEditor::parse(Path::new("SUMMARY.md"), &summary.to_string());
+        assert_eq!(reparsed.unwrap(), summary);
+    }
+
+    #[test]
+    fn invalid_edits_are_rejected() {
+        let mut summary = editor();
+        let unchanged = summary.clone();
+
+        assert!(summary
+            .insert(ChapterLink::new("Again", "./travel.md"), Placement::End)
+            .is_err());
+        assert!(summary
+            .insert(
+                ChapterLink::new("Note", "note.md"),
+                Placement::Under(PathBuf::from("preface.md"))
+            )
+            .is_err());
+        assert!(summary
+            .move_chapter(
+                Path::new("cooking.md"),
+                Placement::Under(PathBuf::from("cooking/bread.md"))
+            )
+            .is_err());
+        match summary.remove(Path::new("missing.md")) {
+            Err(Error::ChapterNotFound(_)) => {}
+            other => panic!("expected a missing chapter, got {:?}", other),
+        }
+        assert_eq!(summary, unchanged);
+    }
+}
@@ -1,84 +1,419 @@
-#![feature(use_extern_macros)]
+use super::*;
 
-#[cfg(test)] extern crate mockers_derive;
-#[cfg(test)] extern crate mockers;
+use crate::git::{GitCall, RecordingBackend, Upstream};
+use crate::prompt::Scripted;
+use crate::reader::Read;
+use mdbook::book::SectionNumber;
+use tempfile::TempDir;
+use termcolor::NoColor;
 
-#[cfg(test)] use self::mockers_derive::mocked;
+fn idea_book(
+    config_dir: &TempDir,
+    replies: &[&str],
+    git: &RecordingBackend,
+) -> IdeaBook<NoColor<Vec<u8>>, &'static [u8]> {
+    IdeaBook {
+        fh: FileHandler::new(config_dir.path().to_path_buf()),
+        printer: Printer {
+            writer: NoColor::new(Vec::new()),
+        },
+        reader: Reader { reader: &b""[..] },
+        index: ChapterIndex::default(),
+        git: Box::new(git.clone()),
+        prompter: Box::new(Scripted::new(replies)),
+        book: BookChoice::Default,
+        // Keep the developer's $EDITOR and $PAGER out of the tests
+        env: Box::new(|_| None),
+    }
+}
 
-use self::mockers::Scenario;
+/// Points the config at a fresh book under `dir` and creates it.
+fn configure(idea_book: &mut IdeaBook<NoColor<Vec<u8>>, &[u8]>, dir: &TempDir) -> String {
+    let repo_path = dir.path().join("ideas").to_string_lossy().into_owned();
+    idea_book.fh.config_write(Repo, repo_path.clone()).unwrap();
+    idea_book
+        .fh
+        .config_write(Author, "Ada".to_string())
+        .unwrap();
+    idea_book
+        .fh
+        .config_write(Title, "Ideas".to_string())
+        .unwrap();
+    repo_path
+}
 
-use std::io::Error;
-use std::io::ErrorKind;
+/// An editor command line that appends `text` to the file and exits.
+fn writing_editor(text: &str) -> String {
+    format!("sh -c 'echo {} >> \"$0\"' {{file}}", text)
+}
 
-use types::ConfigType;
+#[test]
+fn test_is_first_time_run() {
+    let config_dir = TempDir::new().unwrap();
+    let git = RecordingBackend::new();
+    let idea_book = idea_book(&config_dir, &[], &git);
+    assert!(idea_book.is_first_time_run());
 
-use super::*;
+    idea_book
+        .fh
+        .config_write(Repo, "irrelevant-str".to_string())
+        .unwrap();
+    assert!(!idea_book.is_first_time_run());
+}
 
-//    #[test]
-//    fn tests_work() {
-//        let fh = MockFileHandler {};
-//        //        let mut output = Vec::new();
-//        let mut output = StandardStream::stdout(ColorChoice::AlwaysAnsi);
-//        let input = b"I'm George";
-//        let mut reader = Reader { reader: &input[..] };
-//        let mut printer = Printer { writer: output };
-//        run(&fh, &mut printer, &mut reader);
-//
-////        assert_eq!(true, true);
-//    }
-
-//struct MockFhConfigReadErr;
-//struct MockFhConfigReadOk;
-//
-//impl ConfigManagement for MockFhConfigReadErr {
-//    fn config_dir_create(&self) -> Result<String, Error> {
-//        unimplemented!()
-//    }
-//
-//    fn config_dir_exists(&self) -> bool {
-//        unimplemented!()
-//    }
-//
-//    fn config_read(&self, file: ConfigType) -> Result<String, Error> {
-//        Err(io::Error::new(ErrorKind::NotFound, "irrelevant-error-msg"))
-//    }
-//
-//    fn config_write(&self, file: ConfigType, value: &String) -> Result<(), Error> {
-//        unimplemented!()
-//    }
-//}
-//
-//impl ConfigManagement for MockFhConfigReadOk {
-//    fn config_dir_create(&self) -> Result<String, Error> {
-//        unimplemented!()
-//    }
-//
-//    fn config_dir_exists(&self) -> bool {
-//        unimplemented!()
-//    }
-//
-//    fn config_read(&self, file: ConfigType) -> Result<String, Error> {
-//        Ok("irrelevant-str".to_string())
-//    }
-//
-//    fn config_write(&self, file: ConfigType, value: &String) -> Result<(), Error> {
-//        unimplemented!()
-//    }
-//}
+#[test]
+fn setup_book_creates_a_repository_with_an_initial_commit() {
+    let dir = TempDir::new().unwrap();
+    let mut git = RecordingBackend::new();
+    git.has_repo = false;
+    let mut idea_book = idea_book(&dir, &["", "git@example.com:ideas.git"], &git);
+    let repo_path = configure(&mut idea_book, &dir);
+
+    idea_book.setup_book().unwrap();
+
+    assert_eq!(
+        git.calls(),
+        vec![
+            GitCall::Init(repo_path),
+            GitCall::Stage(vec![
+                "book.toml".to_string(),
+                "src/SUMMARY.md".to_string(),
+                ".gitignore".to_string(),
+                "src/chapter_1.md".to_string(),
+            ]),
+            GitCall::Commit("Initial commit".to_string()),
+            GitCall::AddRemote {
+                name: "origin".to_string(),
+                url: "git@example.com:ideas.git".to_string(),
+            },
+        ]
+    );
+}
 
 #[test]
-fn test_is_first_time_run() {
-//    let fh_err = MockFhConfigReadErr {};
-//    let fh_ok = MockFhConfigReadOk {};
+fn setup_book_leaves_git_alone_when_declined() {
+    let dir = TempDir::new().unwrap();
+    let mut git = RecordingBackend::new();
+    git.has_repo = false;
+    let mut idea_book = idea_book(&dir, &["n"], &git);
+    configure(&mut idea_book, &dir);
+
+    idea_book.setup_book().unwrap();
+
+    assert!(git.calls().is_empty());
+}
+
+#[test]
+fn sync_names_the_conflicting_chapters() {
+    let dir = TempDir::new().unwrap();
+    let mut git = RecordingBackend::new();
+    git.conflicts = vec!["src/chapter_1.md".to_string(), "notes.txt".to_string()];
+    let mut idea_book = idea_book(&dir, &[], &git);
+    configure(&mut idea_book, &dir);
+    idea_book.setup_book().unwrap();
+
+    match idea_book.sync() {
+        Err(Error::SyncConflict(paths)) => assert_eq!(
+            paths,
+            vec![
+                "Chapter 1 (src/chapter_1.md)".to_string(),
+                "notes.txt".to_string(),
+            ]
+        ),
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+    assert_eq!(
+        git.calls(),
+        vec![GitCall::Pull(Upstream {
+            remote: "origin".to_string(),
+            branch: "main".to_string(),
+        })]
+    );
+}
+
+#[test]
+fn captures_ideas_as_numbered_sub_chapters() {
+    let dir = TempDir::new().unwrap();
+    let git = RecordingBackend::new();
+    let mut idea_book = idea_book(&dir, &["Sourdough starter"], &git);
+    configure(&mut idea_book, &dir);
+    idea_book
+        .fh
+        .config_write(Editor, writing_editor("Feed it daily"))
+        .unwrap();
+    idea_book.setup_book().unwrap();
+
+    idea_book
+        .run(&ParentChapter::Named("chapter 1".to_string()))
+        .unwrap();
+
+    let book = idea_book.open_book().unwrap();
+    let chapter = book.find_chapter("Sourdough starter").unwrap();
+    assert_eq!(
+        chapter.path,
+        PathBuf::from("chapter_1/sourdough-starter.md")
+    );
+    assert_eq!(chapter.number, Some(SectionNumber(vec![1, 1])));
+    assert_eq!(chapter.parent_names, vec!["Chapter 1".to_string()]);
+    assert_eq!(
+        git.calls(),
+        vec![
+            GitCall::Stage(vec![
+                "src/chapter_1/sourdough-starter.md".to_string(),
+                "src/SUMMARY.md".to_string(),
+            ]),
+            GitCall::Commit("Sourdough starter".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn empty_ideas_are_discarded_without_a_commit() {
+    let dir = TempDir::new().unwrap();
+    let git = RecordingBackend::new();
+    let mut idea_book = idea_book(&dir, &["Sourdough starter"], &git);
+    let repo_path = configure(&mut idea_book, &dir);
+    // Quits without saving anything
+    let editor = get_if_available("true").unwrap();
+    idea_book.fh.config_write(Editor, editor).unwrap();
+    idea_book.setup_book().unwrap();
+    let summary = fs::read_to_string(format!("{}/src/SUMMARY.md", repo_path)).unwrap();
+
+    idea_book
+        .run(&ParentChapter::Named("chapter 1".to_string()))
+        .unwrap();
+
+    assert!(!Path::new(&repo_path).join("src/chapter_1").exists());
+    assert_eq!(
+        fs::read_to_string(format!("{}/src/SUMMARY.md", repo_path)).unwrap(),
+        summary
+    );
+    assert!(git.calls().is_empty());
+
+    // Top level ideas go in the configured subdir, made just for them
+    let book_toml = Path::new(&repo_path).join("book.toml");
+    let mut config = fs::read_to_string(&book_toml).unwrap();
+    config.push_str("\n[sanctuary]\nsubdir = \"inbox/2020\"\n");
+    fs::write(&book_toml, config).unwrap();
+    idea_book.prompter = Box::new(Scripted::new(&["Rye bread"]));
+
+    idea_book.run(&ParentChapter::TopLevel).unwrap();
+
+    assert!(!Path::new(&repo_path).join("src/inbox").exists());
+    assert!(git.calls().is_empty());
+}
+
+#[test]
+fn failing_editors_abort_the_capture() {
+    let dir = TempDir::new().unwrap();
+    let git = RecordingBackend::new();
+    let mut idea_book = idea_book(&dir, &["Sourdough starter"], &git);
+    let repo_path = configure(&mut idea_book, &dir);
+    // Saves the chapter but then crashes
+    let editor = "sh -c 'echo Feed it daily >> \"$0\"; exit 1' {file}";
+    idea_book
+        .fh
+        .config_write(Editor, editor.to_string())
+        .unwrap();
+    idea_book.setup_book().unwrap();
+
+    match idea_book.run(&ParentChapter::TopLevel) {
+        Err(Error::Editor { .. }) => {}
+        other => panic!("expected the editor to fail, got {:?}", other),
+    }
+    assert!(!Path::new(&repo_path)
+        .join("src/sourdough-starter.md")
+        .exists());
+    assert!(git.calls().is_empty());
+}
+
+#[test]
+fn add_writes_the_body_without_an_editor() {
+    let dir = TempDir::new().unwrap();
+    let git = RecordingBackend::new();
+    let mut idea_book = idea_book(&dir, &[], &git);
+    let repo_path = configure(&mut idea_book, &dir);
+    idea_book.setup_book().unwrap();
+
+    idea_book
+        .add(
+            "Rye bread",
+            Some("Needs a longer proof".to_string()),
+            &ParentChapter::Named("chapter 1".to_string()),
+        )
+        .unwrap();
+
+    let text = fs::read_to_string(format!("{}/src/chapter_1/rye-bread.md", repo_path)).unwrap();
+    assert_eq!(text, "Needs a longer proof\n");
+    let book = idea_book.open_book().unwrap();
+    let chapter = book.find_chapter("Rye bread").unwrap();
+    assert_eq!(chapter.number, Some(SectionNumber(vec![1, 1])));
+    assert_eq!(
+        git.calls(),
+        vec![
+            GitCall::Stage(vec![
+                "src/chapter_1/rye-bread.md".to_string(),
+                "src/SUMMARY.md".to_string(),
+            ]),
+            GitCall::Commit("Rye bread".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn reader_reads_piped_bodies_whole() {
+    let mut reader = Reader {
+        reader: &b"First line\nSecond line\n"[..],
+    };
+    assert_eq!(reader.read_all().unwrap(), "First line\nSecond line\n");
+}
+
+#[test]
+fn picks_the_parent_chapter_through_the_prompter() {
+    let dir = TempDir::new().unwrap();
+    let git = RecordingBackend::new();
+    let mut idea_book = idea_book(&dir, &["Chapter 1", "Sourdough starter"], &git);
+    configure(&mut idea_book, &dir);
+    idea_book
+        .fh
+        .config_write(Editor, writing_editor("Feed it daily"))
+        .unwrap();
+    idea_book.setup_book().unwrap();
+
+    idea_book.run(&ParentChapter::Pick).unwrap();
+
+    let book = idea_book.open_book().unwrap();
+    let chapter = book.find_chapter("Sourdough starter").unwrap();
+    assert_eq!(chapter.number, Some(SectionNumber(vec![1, 1])));
+}
+
+#[test]
+fn ideas_go_to_the_chosen_library_book() {
+    let dir = TempDir::new().unwrap();
+    let git = RecordingBackend::new();
+    let mut idea_book = idea_book(&dir, &["trav"], &git);
+    let library_path = idea_book.fh.library_file_path();
+    let mut library = Library::default();
+    for title in &["Kitchen", "Travel"] {
+        let path = dir.path().join(title.to_lowercase());
+        MDBook::create_idea_book(path.clone(), title.to_string(), "Ada".to_string()).unwrap();
+        library.add(&path).unwrap();
+    }
+    library.set_default("kitchen").unwrap();
+    library.save(&library_path).unwrap();
+    let body = || Some("Worth a look".to_string());
+
+    // No repo is configured, the library's default book is used
+    idea_book
+        .add("Rye bread", body(), &ParentChapter::TopLevel)
+        .unwrap();
+    idea_book.book = BookChoice::Named("travel".to_string());
+    idea_book
+        .add("Night trains", body(), &ParentChapter::TopLevel)
+        .unwrap();
+    idea_book.book = BookChoice::Pick;
+    idea_book
+        .add("Ferries", body(), &ParentChapter::TopLevel)
+        .unwrap();
 
-    let scenario = Scenario::new();
-    let mut fh = scenario.create_mock::<ConfigManagement>();
+    assert!(dir.path().join("kitchen/src/rye-bread.md").is_file());
+    assert!(dir.path().join("travel/src/night-trains.md").is_file());
+    assert!(dir.path().join("travel/src/ferries.md").is_file());
+    idea_book.book = BookChoice::Named("garden".to_string());
+    assert!(matches!(idea_book.open_book(), Err(Error::BookNotFound(_))));
+}
+
+#[test]
+fn library_books_still_ask_for_a_missing_editor() {
+    let dir = TempDir::new().unwrap();
+    let git = RecordingBackend::new();
+    let editor = writing_editor("Worth a look");
+    let mut idea_book = idea_book(&dir, &["Other", &editor, "Rye bread"], &git);
+    let library_path = idea_book.fh.library_file_path();
+    let path = dir.path().join("kitchen");
+    MDBook::create_idea_book(path.clone(), "Kitchen".to_string(), "Ada".to_string()).unwrap();
+    let mut library = Library::default();
+    library.add(&path).unwrap();
+    library.set_default("kitchen").unwrap();
+    library.save(&library_path).unwrap();
 
-//    scenario.expect(fh.get_temperature_call().and_return(16));
-//    scenario.expect(fh.make_hotter_call(4).and_return(()));
+    idea_book.run(&ParentChapter::TopLevel).unwrap();
+
+    assert!(idea_book.fh.config_read(Editor).is_ok());
+    assert!(idea_book.fh.config_read(Repo).is_err());
+    let text = fs::read_to_string(path.join("src/rye-bread.md")).unwrap();
+    assert_eq!(text, "Worth a look\n");
+}
+
+#[test]
+fn unnumbered_parents_are_rejected_before_writing() {
+    let dir = TempDir::new().unwrap();
+    let git = RecordingBackend::new();
+    let mut idea_book = idea_book(&dir, &[], &git);
+    let repo_path = configure(&mut idea_book, &dir);
+    idea_book
+        .fh
+        .config_write(Editor, writing_editor("Feed it daily"))
+        .unwrap();
+    idea_book.setup_book().unwrap();
+    let src = Path::new(&repo_path).join("src");
+    fs::write(src.join("intro.md"), "# Intro\n").unwrap();
+    fs::write(
+        src.join("SUMMARY.md"),
+        "# Summary\n\n[Intro](./intro.md)\n\n- [Chapter 1](./chapter_1.md)\n",
+    )
+    .unwrap();
+
+    match idea_book.run(&ParentChapter::Named("intro".to_string())) {
+        Err(Error::Summary { .. }) => {}
+        other => panic!("expected the parent to be rejected, got {:?}", other),
+    }
+    assert!(!src.join("intro").exists());
+    assert!(!src.join("sourdough-starter.md").exists());
+    assert!(git.calls().is_empty());
+}
+
+#[test]
+fn editors_fall_back_to_visual_then_editor() {
+    let dir = TempDir::new().unwrap();
+    let git = RecordingBackend::new();
+    let mut idea_book = idea_book(&dir, &[], &git);
+    let env = |vars: &'static [(&'static str, &'static str)]| {
+        Box::new(move |name: &str| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    };
+
+    idea_book.env = env(&[("VISUAL", "code --wait"), ("EDITOR", "vi")]);
+    assert_eq!(idea_book.editor().unwrap(), "code --wait");
+    idea_book
+        .fh
+        .config_write(Editor, "nano".to_string())
+        .unwrap();
+    assert_eq!(idea_book.editor().unwrap(), "nano");
+    idea_book.clear_editor().unwrap();
+    idea_book.env = env(&[("VISUAL", " "), ("EDITOR", "vi")]);
+    assert_eq!(idea_book.editor().unwrap(), "vi");
+    idea_book.env = env(&[]);
+    assert!(matches!(
+        idea_book.editor(),
+        Err(Error::ConfigMissing(Editor))
+    ));
+}
+
+#[test]
+fn editor_setup_keeps_the_arguments() {
+    let dir = TempDir::new().unwrap();
+    let git = RecordingBackend::new();
+    let mut idea_book = idea_book(&dir, &["Other", "sh -c 'exit 0'"], &git);
 
-    scenario.expect(fh.config_read().and_return(Ok("irrelevant")));
+    idea_book.setup_editor_path().unwrap();
 
-//    assert_eq!(is_first_time_run(&fh), true);
-    assert_eq!(is_first_time_run(&fh), false);
+    let sh = get_if_available("sh").unwrap();
+    assert_eq!(
+        idea_book.fh.config_read(Editor).unwrap(),
+        format!("{} -c 'exit 0'", sh)
+    );
 }
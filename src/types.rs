@@ -1,11 +1,42 @@
+use serde::{Deserialize, Serialize};
+
 pub enum CliFlag {
+    Capture,
+    Add,
+    Open,
     ClearRepo,
     ClearEditor,
     View,
     ShortView,
+    Build,
     BuildBook,
+    Sync,
+    Library,
+}
+
+/// Where `capture` and `add` put a new idea.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParentChapter {
+    /// A chapter of its own at the end of the book.
+    TopLevel,
+    /// A sub-chapter of the chapter with this name or path.
+    Named(String),
+    /// A sub-chapter of a chapter picked interactively.
+    Pick,
+}
+
+/// Which book a command works on.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BookChoice {
+    /// The library's default book, or the configured repo without one.
+    Default,
+    /// The library book with this name or path.
+    Named(String),
+    /// A library book picked interactively.
+    Pick,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ConfigFile {
     Repo,
     Editor,
@@ -14,13 +45,19 @@ pub enum ConfigFile {
 }
 
 impl CliFlag {
-    pub fn value(&self) -> &str {
+    pub fn value(&self) -> &'static str {
         match *self {
+            CliFlag::Capture => "capture",
+            CliFlag::Add => "add",
+            CliFlag::Open => "open",
             CliFlag::ClearRepo => "clear-repo",
             CliFlag::ClearEditor => "clear-editor",
             CliFlag::View => "view",
             CliFlag::ShortView => "v",
+            CliFlag::Build => "build",
             CliFlag::BuildBook => "build-book",
+            CliFlag::Sync => "sync",
+            CliFlag::Library => "library",
         }
     }
 }
@@ -28,11 +65,68 @@ impl CliFlag {
 impl ConfigFile {
     pub fn value(&self) -> &str {
         match *self {
-            // These represents files so underscore is preferred
+            // Names of the legacy per-value files, also used when reporting migrations
             ConfigFile::Repo => "repo_path",
             ConfigFile::Editor => "editor_path",
             ConfigFile::Author => "author_name",
             ConfigFile::Title => "book_title",
         }
     }
+
+    pub fn all() -> [ConfigFile; 4] {
+        [
+            ConfigFile::Repo,
+            ConfigFile::Editor,
+            ConfigFile::Author,
+            ConfigFile::Title,
+        ]
+    }
+}
+
+/// Everything sanctuary remembers between runs, stored as a single TOML file
+/// in the config directory.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Settings {
+    pub repo: Option<String>,
+    /// Command line to write ideas with, e.g. `code --wait` or
+    /// `vim +{line} {file}`. Falls back to `$VISUAL`, then `$EDITOR`.
+    pub editor: Option<String>,
+    pub author: Option<String>,
+    pub title: Option<String>,
+    /// Command line to view chapters with, e.g. `glow -p {path}` or `less -R`.
+    /// Falls back to `$PAGER`, then to rendering chapters in the terminal.
+    pub viewer: Option<String>,
+}
+
+impl Settings {
+    pub fn get(&self, config: ConfigFile) -> Option<&String> {
+        match config {
+            ConfigFile::Repo => self.repo.as_ref(),
+            ConfigFile::Editor => self.editor.as_ref(),
+            ConfigFile::Author => self.author.as_ref(),
+            ConfigFile::Title => self.title.as_ref(),
+        }
+    }
+
+    pub fn set(&mut self, config: ConfigFile, value: Option<String>) {
+        let field = match config {
+            ConfigFile::Repo => &mut self.repo,
+            ConfigFile::Editor => &mut self.editor,
+            ConfigFile::Author => &mut self.author,
+            ConfigFile::Title => &mut self.title,
+        };
+        *field = value;
+    }
+}
+
+/// Per-book settings, kept in the `[sanctuary]` table of the book's `book.toml`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct BookSettings {
+    /// Remote to push ideas to, defaults to the current branch's upstream remote.
+    pub remote: Option<String>,
+    /// Remote branch to push ideas to, defaults to the current branch's upstream.
+    pub branch: Option<String>,
+    /// Directory under `src` new ideas are written to, e.g. `ideas` or
+    /// `inbox/2020`. New ideas go straight into `src` when unset.
+    pub subdir: Option<String>,
 }
@@ -1,5 +1,6 @@
 extern crate which;
 
+use std::path::Path;
 use std::process;
 
 pub fn get_if_available(program: &str) -> Option<String> {
@@ -13,6 +14,72 @@ pub fn get_if_available(program: &str) -> Option<String> {
     }
 }
 
+/// Splits a configured command line such as `glow -p {path}` into the
+/// program and its arguments, filling in the `{name}` placeholders. When the
+/// command line does not use the first placeholder, its value is appended as
+/// the last argument, so `less -R` works as well as `less -R {path}`. A line
+/// naming an existing file is a bare program, as older configs stored them.
+pub fn command_line(line: &str, placeholders: &[(&str, &str)]) -> Result<Vec<String>, String> {
+    let mut args = if Path::new(line).is_file() {
+        vec![line.to_string()]
+    } else {
+        shell_words::split(line).map_err(|e| e.to_string())?
+    };
+    if args.is_empty() {
+        return Err("the command line is empty".to_string());
+    }
+
+    let mut substituted = false;
+    for arg in args.iter_mut() {
+        for (position, (name, value)) in placeholders.iter().enumerate() {
+            let placeholder = format!("{{{}}}", name);
+            if arg.contains(&placeholder) {
+                *arg = arg.replace(&placeholder, value);
+                substituted |= position == 0;
+            }
+        }
+    }
+    if !substituted {
+        if let Some((_, value)) = placeholders.first() {
+            args.push(value.to_string());
+        }
+    }
+    Ok(args)
+}
+
 pub fn exit_w_code(code: i32) {
     process::exit(code);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_lines_fill_in_or_append_placeholders() {
+        let path = [("path", "notes/a b.md")];
+        assert_eq!(
+            command_line("glow -p {path}", &path).unwrap(),
+            vec!["glow", "-p", "notes/a b.md"]
+        );
+        assert_eq!(
+            command_line("less -R", &path).unwrap(),
+            vec!["less", "-R", "notes/a b.md"]
+        );
+        assert_eq!(
+            command_line("'my viewer' --file={path}", &path).unwrap(),
+            vec!["my viewer", "--file=notes/a b.md"]
+        );
+        let file = [("file", "idea.md"), ("line", "12")];
+        assert_eq!(
+            command_line("vim +{line}", &file).unwrap(),
+            vec!["vim", "+12", "idea.md"]
+        );
+        assert_eq!(
+            command_line("code --wait --goto {file}:{line}", &file).unwrap(),
+            vec!["code", "--wait", "--goto", "idea.md:12"]
+        );
+        assert!(command_line("  ", &path).is_err());
+        assert!(command_line("less 'unclosed", &path).is_err());
+    }
+}